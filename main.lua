@@ -1,6 +1,19 @@
+-- Filled in by the server when serving this file
+api_url = "{{api_url}}"
+script_version = "{{script_version}}"
+poll_interval = {{poll_interval}}
+fuel_threshold = {{fuel_threshold}}
+use_gps = {{use_gps}}
+ores = {{ores}}
+
+-- Blocks dug since the start of the batch, by name
+dug = {}
+-- Steps done by the move running, to locate the ores it digs
+command_steps = 0
+
 _G.RefuelCheck = function()
     local fuelLevel = turtle.getFuelLevel()
-    if fuelLevel < 500 then
+    if fuelLevel < fuel_threshold then
         turtle.select(16)
         local amount = math.ceil(turtle.getItemCount(16) - 1)
         if amount > 0 then
@@ -11,16 +24,43 @@ _G.RefuelCheck = function()
     return fuelLevel
 end
 
-_G.isFull = function ()
-  local isFull = true
-  for i = 1, 15 do
-    turtle.select(i)
-    if turtle.getItemCount() == 0 then
-      isFull = false
-      break
+-- Send the content of the 16 slots, the server decides when to deposit
+_G.ReportInventory = function()
+    local slots = {}
+    for i = 1, 16 do
+        local item = turtle.getItemDetail(i)
+        if item then
+            slots[i] = { name = item.name, count = item.count }
+        else
+            slots[i] = textutils.json_null
+        end
     end
-  end
-  return isFull
+    local body = textutils.serializeJSON({ slots = slots })
+    http.post(api_url .. "/inventory/" .. turtlename, body, { ["Content-Type"] = "application/json" })
+end
+
+-- Tell the server the capacity of the chest in front: its free slots, or the items it holds
+_G.ReportStation = function(items)
+    local chest = peripheral.wrap("front")
+    if not chest or not chest.list then
+        return
+    end
+    local capacity = 0
+    if items then
+        for _, item in pairs(chest.list()) do
+            capacity = capacity + item.count
+        end
+    else
+        capacity = chest.size()
+        for _ in pairs(chest.list()) do
+            capacity = capacity - 1
+        end
+    end
+    local body = "capacity=" .. capacity
+    if batch_id then
+        body = body .. "&batch=" .. batch_id .. "&done=" .. batch_done
+    end
+    http.post(api_url .. "/station/" .. turtlename, body)
 end
 
 chestName = "minecraft:chest"
@@ -28,41 +68,76 @@ _G.DepositItem = function(filter)
     local has_block, inspect = turtle.inspect()
     if has_block and inspect.name == chestName then
       print("Start item deposit")
-      turtle.inspect()
       for i = 1, 15 do
           turtle.select(i)
-          while turtle.getItemCount() > 0 do
-            turtle.drop()
+          -- Stops once the chest is full
+          while turtle.getItemCount() > 0 and turtle.drop() do
           end
       end
+      ReportStation(false)
     else
-      info("issue", "missing chest")
+      info("last_error", "missing chest")
       print("Missing chest")
     end
 end
 
+-- Fill the fuel slot from the chest in front
+_G.Refuel = function()
+    turtle.select(16)
+    local space = turtle.getItemSpace(16)
+    if space > 0 then
+        turtle.suck(space)
+    end
+    RefuelCheck()
+    ReportStation(true)
+end
+
+-- Dig the block on a side of the turtle, counting it and reporting it to the server when it is an ore
+_G.Dig = function(side, inspect, dig)
+    local has_block, data = inspect()
+    if not dig() then
+        return false
+    end
+    if has_block then
+        dug[data.name] = (dug[data.name] or 0) + 1
+        if ores[data.name] and batch_id then
+            http.post(api_url .. "/dug/" .. turtlename, "side=" .. side .. "&name=" .. data.name .. "&batch=" .. batch_id .. "&done=" .. batch_done .. "&steps=" .. command_steps)
+        end
+    end
+    return true
+end
+
+-- Moves return false and the number of blocks moved when they fail
 _G.Up = function(height)
     for i = 1, height do
+        command_steps = i - 1
         while turtle.detectUp() do
-          if not turtle.digUp() then
+          if not _G.Dig("up", turtle.inspectUp, turtle.digUp) then
             info("stuck", "up")
             break
           end
         end
-        turtle.up()
+        if not turtle.up() then
+          return false, i - 1
+        end
     end
+    return true
 end
 
 _G.Down = function(height)
     for i = 1, height do
+        command_steps = i - 1
         while turtle.detectDown() do
-            if not turtle.digDown() then
+            if not _G.Dig("down", turtle.inspectDown, turtle.digDown) then
               info("stuck", "down")
               break
             end
         end
-        turtle.down()
+        if not turtle.down() then
+          return false, i - 1
+        end
     end
+    return true
 end
 
 _G.Left = function(n)
@@ -95,63 +170,203 @@ end
 --     end
 -- end
 
+local function forwardStep()
+    while turtle.detect() do
+        if not _G.Dig("front", turtle.inspect, turtle.dig) then
+          info("stuck", "forward")
+          break
+        end
+    end
+    return turtle.forward()
+end
+
 _G.Forward = function(x)
     for i = 1, x do
-        while turtle.detect() do
-            if not turtle.dig() then
-              info("stuck", "forward")
-              break
-            end
+        command_steps = i - 1
+        if not forwardStep() then
+          return false, i - 1
         end
-        turtle.forward()
     end
+    return true
 end
 
 _G.ForwardDig = function(height)
   for i = 1, height do
+      command_steps = i - 1
       while turtle.detectUp() do
-          if not turtle.digUp() then
+          if not _G.Dig("up", turtle.inspectUp, turtle.digUp) then
             break
           end
       end
       while turtle.detectDown() do
-        if not turtle.digDown() then
+        if not _G.Dig("down", turtle.inspectDown, turtle.digDown) then
           break
         end
       end
-      _G.Forward(1)
+      if not forwardStep() then
+        return false, i - 1
+      end
+  end
+  return true
+end
+
+-- Tunnels two blocks high
+_G.ForwardDigUp = function(length)
+  for i = 1, length do
+      command_steps = i - 1
+      while turtle.detectUp() do
+          if not _G.Dig("up", turtle.inspectUp, turtle.digUp) then
+            break
+          end
+      end
+      if not forwardStep() then
+        return false, i - 1
+      end
   end
+  return true
 end
 
 
 _G.Sleep = sleep
 
 _G.Reboot = function()
+    -- The batch would never be acknowledged otherwise
+    batch_done = batch_done + 1
+    _G.Acknowledge(0)
     shell.execute("reboot")
 end
 
+_G.Update = function()
+    local request = http.get(api_url .. "/luafile")
+    if not request then
+        info("last_error", "update failed")
+        return
+    end
+    local file = fs.open(shell.getRunningProgram(), "w")
+    file.write(request.readAll())
+    file.close()
+    request.close()
+    _G.Reboot()
+end
+
 
 
-api_url = "http://e61e-2001-861-3f0a-ec00-6870-79a6-b4e0-d8cd.ngrok.io"
 _G.info = function(topic, info)
     local request = http.post(api_url .. "/info/" .. turtlename .. '/' .. topic, "info=" .. tostring(info))
     return request
 end
 
+-- First line of the orders, the commands that follow are acknowledged once run
+_G.Batch = function(id)
+    batch_id = id
+    -- The main loop counts this line as done
+    batch_done = -1
+    dug = {}
+end
+
+-- Report the GPS position, and the heading when `heading` is 1 by moving forward and back.
+-- Returns false when the server answers it doesn't match the position it expected
+_G.Locate = function(heading)
+    local x, y, z = gps.locate(2)
+    if not x then
+        print("No GPS fix")
+        return true
+    end
+    local direction = nil
+    if heading == 1 and turtle.forward() then
+        local x2, y2, z2 = gps.locate(2)
+        if x2 then
+            if z2 < z then direction = "North"
+            elseif x2 > x then direction = "East"
+            elseif z2 > z then direction = "South"
+            elseif x2 < x then direction = "West"
+            end
+        end
+        -- Stuck one block ahead
+        if not turtle.back() and x2 then
+            x, y, z = x2, y2, z2
+        end
+    end
+    local body = "x=" .. x .. "&y=" .. y .. "&z=" .. z
+    if direction then
+        body = body .. "&heading=" .. direction
+    end
+    if batch_id then
+        body = body .. "&batch=" .. batch_id .. "&done=" .. batch_done
+    end
+    local request = http.post(api_url .. "/gps/" .. turtlename, body)
+    if request and request.readAll() == "replan" then
+        return false
+    end
+    return true
+end
+
+-- Report the block on a side of the turtle, "minecraft:air" when there is none
+_G.InspectSide = function(side, inspect)
+    local has_block, data = inspect()
+    local name = "minecraft:air"
+    if has_block then
+        name = data.name
+    end
+    local body = "side=" .. side .. "&name=" .. name
+    if batch_id then
+        body = body .. "&batch=" .. batch_id .. "&done=" .. batch_done
+    end
+    http.post(api_url .. "/inspect/" .. turtlename, body)
+end
+
+_G.Inspect = function()
+    _G.InspectSide("front", turtle.inspect)
+end
+
+_G.InspectUp = function()
+    _G.InspectSide("up", turtle.inspectUp)
+end
+
+_G.InspectDown = function()
+    _G.InspectSide("down", turtle.inspectDown)
+end
+
+-- Tell the server how many commands of the batch were done, the steps of the one that failed and
+-- the blocks dug
+_G.Acknowledge = function(steps)
+    if batch_id then
+        local dug_list = {}
+        for name, count in pairs(dug) do
+            table.insert(dug_list, name .. "*" .. count)
+        end
+        http.post(api_url .. "/ack/" .. turtlename, "batch=" .. batch_id .. "&done=" .. batch_done .. "&steps=" .. steps .. "&fuel=" .. turtle.getFuelLevel() .. "&dug=" .. table.concat(dug_list, ","))
+        batch_id = nil
+        dug = {}
+    end
+end
+
 
 turtlename = os.getComputerLabel()
 while 1 do
-    info("fuellevel", _G.RefuelCheck())
-    info("isFull", _G.isFull())
-    local request = http.get(api_url .. "/request/" .. turtlename)
+    if use_gps then
+        _G.Locate(0)
+    end
+    info("fuel_level", _G.RefuelCheck())
+    info("fuel_limit", turtle.getFuelLimit())
+    info("selected_slot", turtle.getSelectedSlot())
+    _G.ReportInventory()
+    local request = http.get(api_url .. "/request/" .. turtlename .. "?version=" .. script_version)
+    batch_id = nil
+    batch_done = 0
+    local failed_steps = 0
     for line in request.readLine do
         print("Command from server:" .. line)
-        if not pcall(loadstring(line)) then
+        local ok, result, steps = pcall(loadstring("return " .. line))
+        if not ok or result == false then
             print("Coulnd't do: " .. line)
+            failed_steps = steps or 0
             break
         end
+        batch_done = batch_done + 1
     end
-    sleep(2)
+    _G.Acknowledge(failed_steps)
+    sleep(poll_interval)
 end
 
 
@@ -0,0 +1,731 @@
+use actix_web::{
+    delete,
+    error::{InternalError, JsonPayloadError, QueryPayloadError},
+    get,
+    http::StatusCode,
+    post, put, web, HttpRequest, HttpResponse, ResponseError,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    config::{Config, WorldConfig},
+    fuel::{self, FuelProjection},
+    inventory::Inventory,
+    locks::TurtleLocks,
+    mining_areas::{area_world, MiningArea, DEFAULT_AREA},
+    mining_plots::{MiningPlot, SegmentYield},
+    order_parser::{check_strategy_argument, parse_orders, parse_strategy, ParseError},
+    order_queue::{OrderQueue, Priority, QueuedOrder},
+    persistance::{Storage, StorageError},
+    stations::{Station, StationKind},
+    telemetry::{self, Telemetry, TelemetryPoint, TelemetryTopic},
+    turtle::{Command, CommandName, Drift, Turtle},
+    utils::{unix_timestamp, Area, Direction, Position},
+    world_map::{self, Block, MAX_REGION_VOLUME},
+};
+
+/// Errors returned by the JSON API, rendered as `{"error": "..."}` with a matching status code.
+#[derive(Debug)]
+pub enum ApiError {
+    TurtleNotFound(String),
+    InfoNotFound(String, String),
+    PlotNotFound(u32),
+    AreaNotFound(String),
+    StationNotFound(String),
+    InventoryNotFound(String),
+    TopicNotFound(String, String),
+    OrderNotFound(String, u32),
+    InvalidOrders(Vec<ParseError>),
+    InvalidBody(String),
+    InvalidQuery(String),
+    Database(StorageError),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::TurtleNotFound(name) => write!(f, "No turtle with name: {} found", name),
+            ApiError::InfoNotFound(name, topic) => {
+                write!(f, "Turtle {} has no info on topic: {}", name, topic)
+            }
+            ApiError::PlotNotFound(id) => write!(f, "No mining plot with id: {} found", id),
+            ApiError::AreaNotFound(name) => write!(f, "No mining area with name: {} found", name),
+            ApiError::StationNotFound(name) => write!(f, "No station with name: {} found", name),
+            ApiError::InventoryNotFound(name) => {
+                write!(f, "Turtle {} didn't report its inventory", name)
+            }
+            ApiError::TopicNotFound(name, topic) => {
+                write!(f, "Turtle {} never posted on topic: {}", name, topic)
+            }
+            ApiError::OrderNotFound(name, id) => {
+                write!(f, "Turtle {} has no queued order with id: {}", name, id)
+            }
+            ApiError::InvalidOrders(errors) => write!(f, "{} invalid order(s)", errors.len()),
+            ApiError::InvalidBody(message) => write!(f, "Invalid body: {}", message),
+            ApiError::InvalidQuery(message) => write!(f, "Invalid query: {}", message),
+            ApiError::Database(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<StorageError> for ApiError {
+    fn from(error: StorageError) -> Self {
+        ApiError::Database(error)
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<&'a [ParseError]>,
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::TurtleNotFound(_)
+            | ApiError::InfoNotFound(_, _)
+            | ApiError::PlotNotFound(_)
+            | ApiError::AreaNotFound(_)
+            | ApiError::StationNotFound(_)
+            | ApiError::InventoryNotFound(_)
+            | ApiError::TopicNotFound(_, _)
+            | ApiError::OrderNotFound(_, _) => StatusCode::NOT_FOUND,
+            ApiError::InvalidOrders(_) | ApiError::InvalidBody(_) | ApiError::InvalidQuery(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ApiError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let details = match self {
+            ApiError::InvalidOrders(errors) => Some(errors.as_slice()),
+            _ => None,
+        };
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            error: self.to_string(),
+            details,
+        })
+    }
+}
+
+type ApiResult<T> = Result<web::Json<T>, ApiError>;
+
+async fn find_turtle(storage: &dyn Storage, name: &str) -> Result<Turtle, ApiError> {
+    storage
+        .find_turtle(name)
+        .await?
+        .ok_or_else(|| ApiError::TurtleNotFound(name.to_string()))
+}
+
+#[get("/turtles")]
+async fn list_turtles(storage: web::Data<dyn Storage>) -> ApiResult<Vec<Turtle>> {
+    Ok(web::Json(storage.list_turtles().await?))
+}
+
+#[get("/turtles/{name}")]
+async fn get_turtle(path: web::Path<String>, storage: web::Data<dyn Storage>) -> ApiResult<Turtle> {
+    Ok(web::Json(find_turtle(storage.as_ref(), &path).await?))
+}
+
+/// The queued orders of a turtle, in the order they run.
+#[get("/turtles/{name}/orders")]
+async fn get_orders(
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<Vec<QueuedOrder>> {
+    let turtle = find_turtle(storage.as_ref(), &path).await?;
+    Ok(web::Json(turtle.queue.orders().to_vec()))
+}
+
+/// Argument of an order, the name of a strategy for `MinePlot`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Argument {
+    Number(i32),
+    Name(String),
+}
+
+/// An order as JSON, e.g. `{"name": "MinePlot", "argument": "BranchMining"}`. Without an argument
+/// the default of the command is used, as in the order language.
+#[derive(Deserialize)]
+struct Order {
+    name: CommandName,
+    argument: Option<Argument>,
+}
+
+impl Order {
+    fn command(self) -> Result<Command, String> {
+        let argument = match (&self.name, self.argument) {
+            (name, None) => name.default_argument(),
+            (CommandName::MinePlot, Some(Argument::Name(strategy))) => {
+                parse_strategy(&strategy)?.argument()
+            }
+            (CommandName::MinePlot, Some(Argument::Number(value))) => {
+                check_strategy_argument(value)?
+            }
+            (name, Some(Argument::Name(argument))) => {
+                return Err(format!(
+                    "invalid argument `{}` for order {}, expected a number",
+                    argument, name
+                ))
+            }
+            (name, Some(Argument::Number(value))) if value < 0 => {
+                return Err(format!("negative argument `{}` for order {}", value, name))
+            }
+            (_, Some(Argument::Number(value))) => value,
+        };
+        Ok(Command::new(self.name, argument))
+    }
+}
+
+/// Orders can be sent either in the order language or as a list of commands.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Orders {
+    Program(String),
+    Commands(Vec<Order>),
+}
+
+#[derive(Deserialize)]
+struct OrdersBody {
+    orders: Orders,
+    #[serde(default)]
+    priority: Priority,
+    /// Queue the orders ahead of the ones of the same priority, only when adding orders.
+    #[serde(default)]
+    prepend: bool,
+}
+
+impl OrdersBody {
+    fn commands(self) -> Result<Vec<Command>, ApiError> {
+        match self.orders {
+            Orders::Program(orders) => {
+                parse_orders(&orders).map_err(|errors| ApiError::InvalidOrders(errors.0))
+            }
+            Orders::Commands(orders) => orders
+                .into_iter()
+                .map(Order::command)
+                .collect::<Result<_, _>>()
+                .map_err(ApiError::InvalidBody),
+        }
+    }
+}
+
+/// Apply `edit` to the queue of a turtle and save it.
+async fn edit_queue<T>(
+    storage: &dyn Storage,
+    locks: &TurtleLocks,
+    name: &str,
+    edit: impl FnOnce(&mut OrderQueue) -> Result<T, ApiError>,
+) -> Result<T, ApiError> {
+    let lock = locks.turtle(name);
+    let _guard = lock.lock().await;
+    let mut turtle = find_turtle(storage, name).await?;
+    let result = edit(&mut turtle.queue)?;
+    storage.set_turtle_queue(name, &turtle.queue).await?;
+    Ok(result)
+}
+
+/// Replace every queued order.
+#[put("/turtles/{name}/orders")]
+async fn set_orders(
+    path: web::Path<String>,
+    body: web::Json<OrdersBody>,
+    storage: web::Data<dyn Storage>,
+    locks: web::Data<TurtleLocks>,
+) -> ApiResult<Vec<QueuedOrder>> {
+    let name = path.into_inner();
+    let priority = body.priority;
+    let orders = body.into_inner().commands()?;
+    log::info!("Replacing the orders of {}", name);
+    let queued = edit_queue(storage.as_ref(), &locks, &name, |queue| {
+        Ok(queue.replace(orders, priority))
+    })
+    .await?;
+    Ok(web::Json(queued))
+}
+
+/// Queue orders after the ones of the same priority, or ahead of them with `prepend`.
+#[post("/turtles/{name}/orders")]
+async fn add_orders(
+    path: web::Path<String>,
+    body: web::Json<OrdersBody>,
+    storage: web::Data<dyn Storage>,
+    locks: web::Data<TurtleLocks>,
+) -> ApiResult<Vec<QueuedOrder>> {
+    let name = path.into_inner();
+    let (priority, prepend) = (body.priority, body.prepend);
+    let orders = body.into_inner().commands()?;
+    log::info!("Adding {} orders for {}", priority, name);
+    let queued = edit_queue(storage.as_ref(), &locks, &name, |queue| {
+        Ok(if prepend {
+            queue.prepend(orders, priority)
+        } else {
+            queue.append(orders, priority)
+        })
+    })
+    .await?;
+    Ok(web::Json(queued))
+}
+
+/// Cancel a queued order, the ones already sent to the turtle run anyway.
+#[delete("/turtles/{name}/orders/{id}")]
+async fn cancel_order(
+    path: web::Path<(String, u32)>,
+    storage: web::Data<dyn Storage>,
+    locks: web::Data<TurtleLocks>,
+) -> ApiResult<QueuedOrder> {
+    let (name, id) = path.into_inner();
+    log::info!("Cancelling order {} of {}", id, name);
+    let cancelled = edit_queue(storage.as_ref(), &locks, &name, |queue| {
+        queue
+            .cancel(id)
+            .ok_or_else(|| ApiError::OrderNotFound(name.clone(), id))
+    })
+    .await?;
+    Ok(web::Json(cancelled))
+}
+
+#[get("/turtles/{name}/telemetry")]
+async fn get_telemetry(
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<Telemetry> {
+    Ok(web::Json(
+        find_turtle(storage.as_ref(), &path).await?.telemetry,
+    ))
+}
+
+/// Range of a telemetry history, `from` a day before `to` and `to` now by default. The samples
+/// are grouped in periods of `step` seconds when given.
+#[derive(Deserialize)]
+struct HistoryQuery {
+    from: Option<u64>,
+    to: Option<u64>,
+    step: Option<u64>,
+}
+
+/// Most periods a history can be downsampled to, a day per minute and then some.
+const MAX_HISTORY_PERIODS: u64 = 10_000;
+
+impl HistoryQuery {
+    fn range(&self) -> Result<(u64, u64), ApiError> {
+        let to = self.to.unwrap_or_else(unix_timestamp);
+        let from = self.from.unwrap_or(to.saturating_sub(24 * 3600));
+        if from > to {
+            return Err(ApiError::InvalidQuery(format!(
+                "from ({}) is after to ({})",
+                from, to
+            )));
+        }
+        match self.step {
+            Some(0) => Err(ApiError::InvalidQuery(
+                "step must be at least 1".to_string(),
+            )),
+            Some(step) if (to - from) / step >= MAX_HISTORY_PERIODS => {
+                Err(ApiError::InvalidQuery(format!(
+                    "the range holds more than {} periods of {} seconds",
+                    MAX_HISTORY_PERIODS, step
+                )))
+            }
+            _ => Ok((from, to)),
+        }
+    }
+}
+
+/// Values posted by a turtle on a topic over time, e.g. its fuel level per minute over the last
+/// day with `?step=60`.
+#[get("/turtles/{name}/telemetry/{topic}")]
+async fn get_telemetry_history(
+    path: web::Path<(String, String)>,
+    query: web::Query<HistoryQuery>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<Vec<TelemetryPoint>> {
+    let (name, topic) = path.into_inner();
+    let (from, to) = query.range()?;
+    let turtle = find_turtle(storage.as_ref(), &name).await?;
+    let topic = match TelemetryTopic::from_name(&topic) {
+        Some(topic) => topic.to_string(),
+        None if turtle.infos.contains_key(&topic) => topic,
+        None => return Err(ApiError::TopicNotFound(name, topic)),
+    };
+    let samples = storage
+        .find_telemetry_samples(&name, &topic, from, to)
+        .await?;
+    Ok(web::Json(telemetry::downsample(&samples, from, query.step)))
+}
+
+/// The custom infos, the telemetry aside.
+#[get("/turtles/{name}/infos")]
+async fn get_infos(
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<HashMap<String, String>> {
+    Ok(web::Json(find_turtle(storage.as_ref(), &path).await?.infos))
+}
+
+#[derive(Serialize, Deserialize)]
+struct Info {
+    topic: String,
+    info: String,
+}
+
+#[get("/turtles/{name}/infos/{topic}")]
+async fn get_info(
+    path: web::Path<(String, String)>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<Info> {
+    let (name, topic) = path.into_inner();
+    let turtle = find_turtle(storage.as_ref(), &name).await?;
+    match turtle.info(&topic) {
+        Some(info) => Ok(web::Json(Info { info, topic })),
+        None => Err(ApiError::InfoNotFound(name, topic)),
+    }
+}
+
+/// The last inventory reported by a turtle.
+#[get("/turtles/{name}/inventory")]
+async fn get_inventory(
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<Inventory> {
+    let name = path.into_inner();
+    match find_turtle(storage.as_ref(), &name).await?.inventory {
+        Some(inventory) => Ok(web::Json(inventory)),
+        None => Err(ApiError::InventoryNotFound(name)),
+    }
+}
+
+/// Fuel left to a turtle once it ran its pending batch and its queued orders.
+#[get("/turtles/{name}/fuel")]
+async fn get_fuel(
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+) -> ApiResult<FuelProjection> {
+    let turtle = find_turtle(storage.as_ref(), &path).await?;
+    let world = area_world(storage.as_ref(), &config.world, &turtle.area).await?;
+    let stations = storage.list_stations().await?;
+    Ok(web::Json(fuel::project(&turtle, &stations, &world)))
+}
+
+#[get("/turtles/{name}/drifts")]
+async fn get_drifts(
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<Vec<Drift>> {
+    Ok(web::Json(
+        find_turtle(storage.as_ref(), &path).await?.drifts,
+    ))
+}
+
+async fn find_plot(storage: &dyn Storage, id: u32) -> Result<MiningPlot, ApiError> {
+    storage
+        .find_plot(id)
+        .await?
+        .ok_or(ApiError::PlotNotFound(id))
+}
+
+#[get("/mining_plots")]
+async fn list_mining_plots(storage: web::Data<dyn Storage>) -> ApiResult<Vec<MiningPlot>> {
+    Ok(web::Json(storage.list_plots().await?))
+}
+
+/// Blocks dug in a plot, in total and per depth segment.
+#[derive(Serialize)]
+struct PlotYields {
+    plot: u32,
+    total: HashMap<String, u32>,
+    segments: Vec<SegmentYield>,
+}
+
+#[get("/mining_plots/{id}/yields")]
+async fn get_plot_yields(
+    path: web::Path<u32>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<PlotYields> {
+    let id = path.into_inner();
+    let plot = find_plot(storage.as_ref(), id).await?;
+    let mut segments = plot.yields.clone();
+    segments.sort_by_key(|segment| segment.segment);
+    Ok(web::Json(PlotYields {
+        plot: id,
+        total: plot.total_yields(),
+        segments,
+    }))
+}
+
+/// Turtle mining a plot, `null` to release the plot.
+#[derive(Deserialize)]
+struct PlotTurtle {
+    turtle: Option<String>,
+}
+
+/// Give a plot to a turtle, which leaves its current plot to the pool and moves to the area of the
+/// plot, or release it. A finished plot given to a turtle is mined again from its next segment.
+#[put("/mining_plots/{id}/turtle")]
+async fn set_plot_turtle(
+    path: web::Path<u32>,
+    body: web::Json<PlotTurtle>,
+    storage: web::Data<dyn Storage>,
+    locks: web::Data<TurtleLocks>,
+) -> ApiResult<MiningPlot> {
+    let id = path.into_inner();
+    let turtle = body.into_inner().turtle;
+    loop {
+        // Neither the turtle holding the plot nor the new one must plan from their plots meanwhile
+        let holder = find_plot(storage.as_ref(), id).await?.current_turtle;
+        let turtle_locks = locks.turtles(holder.iter().chain(&turtle).map(String::as_str));
+        let mut _guards = Vec::new();
+        for lock in &turtle_locks {
+            _guards.push(lock.lock().await);
+        }
+        let mut plot = find_plot(storage.as_ref(), id).await?;
+        if plot.current_turtle != holder {
+            // Taken by another turtle while waiting, which isn't locked
+            continue;
+        }
+        if let Some(name) = &turtle {
+            find_turtle(storage.as_ref(), name).await?;
+            storage.release_turtle_plot(name).await?;
+            storage.set_turtle_area(name, &plot.area).await?;
+            plot.finished = false;
+            log::info!("Giving mining plot {} to {}", id, name);
+        } else {
+            log::info!("Releasing mining plot {}", id);
+        }
+        plot.current_turtle = turtle;
+        storage.save_plot(&plot).await?;
+        return Ok(web::Json(plot));
+    }
+}
+
+async fn find_area(
+    storage: &dyn Storage,
+    world: &WorldConfig,
+    name: &str,
+) -> Result<MiningArea, ApiError> {
+    if name == DEFAULT_AREA {
+        return Ok(MiningArea::from_world(world));
+    }
+    storage
+        .find_area(name)
+        .await?
+        .ok_or_else(|| ApiError::AreaNotFound(name.to_string()))
+}
+
+/// The default area first, then the ones created through the API.
+#[get("/mining_areas")]
+async fn list_mining_areas(
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+) -> ApiResult<Vec<MiningArea>> {
+    let mut areas = vec![MiningArea::from_world(&config.world)];
+    areas.extend(storage.list_areas().await?);
+    Ok(web::Json(areas))
+}
+
+#[get("/mining_areas/{name}")]
+async fn get_mining_area(
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+) -> ApiResult<MiningArea> {
+    Ok(web::Json(
+        find_area(storage.as_ref(), &config.world, &path).await?,
+    ))
+}
+
+/// A mining area, named by the path.
+#[derive(Deserialize)]
+struct AreaBody {
+    origin: Position,
+    plot_size: i32,
+    min_y: i32,
+    direction: Direction,
+    chest_position: Position,
+}
+
+/// Create or replace a mining area. Replacing an area moves its next plots, not the existing ones.
+#[put("/mining_areas/{name}")]
+async fn set_mining_area(
+    path: web::Path<String>,
+    body: web::Json<AreaBody>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<MiningArea> {
+    let name = path.into_inner();
+    if name == DEFAULT_AREA {
+        return Err(ApiError::InvalidBody(format!(
+            "the {} area is set in the [world] config",
+            DEFAULT_AREA
+        )));
+    }
+    let body = body.into_inner();
+    let area = MiningArea {
+        name,
+        origin: body.origin,
+        plot_size: body.plot_size,
+        min_y: body.min_y,
+        direction: body.direction,
+        chest_position: body.chest_position,
+    };
+    area.validate().map_err(ApiError::InvalidBody)?;
+    log::info!("Saving mining area {}", area.name);
+    storage.save_area(&area).await?;
+    Ok(web::Json(area))
+}
+
+#[derive(Deserialize)]
+struct TurtleArea {
+    area: String,
+}
+
+/// Move a turtle to a mining area, it leaves its current plot once done with its batch.
+#[put("/turtles/{name}/area")]
+async fn set_turtle_area(
+    path: web::Path<String>,
+    body: web::Json<TurtleArea>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+    locks: web::Data<TurtleLocks>,
+) -> ApiResult<MiningArea> {
+    let name = path.into_inner();
+    let area = find_area(storage.as_ref(), &config.world, &body.area).await?;
+    log::info!("Moving {} to the {} area", name, area.name);
+    let lock = locks.turtle(&name);
+    let _guard = lock.lock().await;
+    if storage.set_turtle_area(&name, &area.name).await? {
+        Ok(web::Json(area))
+    } else {
+        Err(ApiError::TurtleNotFound(name))
+    }
+}
+
+#[get("/stations")]
+async fn list_stations(storage: web::Data<dyn Storage>) -> ApiResult<Vec<Station>> {
+    Ok(web::Json(storage.list_stations().await?))
+}
+
+/// A station, named by the path.
+#[derive(Deserialize)]
+struct StationBody {
+    kind: StationKind,
+    position: Position,
+    facing: Direction,
+}
+
+/// Create or replace a station. Its capacity is unknown until a turtle uses it.
+#[put("/stations/{name}")]
+async fn set_station(
+    path: web::Path<String>,
+    body: web::Json<StationBody>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<Station> {
+    let body = body.into_inner();
+    let station = Station {
+        name: path.into_inner(),
+        kind: body.kind,
+        position: body.position,
+        facing: body.facing,
+        capacity: None,
+    };
+    log::info!("Saving {} station {}", station.kind, station.name);
+    storage.save_station(&station).await?;
+    Ok(web::Json(station))
+}
+
+#[delete("/stations/{name}")]
+async fn delete_station(
+    path: web::Path<String>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<Station> {
+    let name = path.into_inner();
+    let station = storage
+        .list_stations()
+        .await?
+        .into_iter()
+        .find(|station| station.name == name)
+        .ok_or_else(|| ApiError::StationNotFound(name.clone()))?;
+    log::info!("Deleting station {}", name);
+    if storage.delete_station(&name).await? {
+        Ok(web::Json(station))
+    } else {
+        Err(ApiError::StationNotFound(name))
+    }
+}
+
+/// Two opposite corners of a region, as `x,y,z`.
+#[derive(Deserialize)]
+struct RegionQuery {
+    from: String,
+    to: String,
+}
+
+impl RegionQuery {
+    fn area(&self) -> Result<Area, ApiError> {
+        let from: Position = self.from.parse().map_err(ApiError::InvalidQuery)?;
+        let to: Position = self.to.parse().map_err(ApiError::InvalidQuery)?;
+        let area = Area::new(from, to);
+        if world_map::volume(&area) > MAX_REGION_VOLUME {
+            return Err(ApiError::InvalidQuery(format!(
+                "the region holds more than {} blocks",
+                MAX_REGION_VOLUME
+            )));
+        }
+        Ok(area)
+    }
+}
+
+#[get("/blocks")]
+async fn list_blocks(
+    query: web::Query<RegionQuery>,
+    storage: web::Data<dyn Storage>,
+) -> ApiResult<Vec<Block>> {
+    Ok(web::Json(storage.find_blocks(&query.area()?).await?))
+}
+
+fn json_error_handler(error: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    let api_error = ApiError::InvalidBody(error.to_string());
+    let response = api_error.error_response();
+    InternalError::from_response(error, response).into()
+}
+
+fn query_error_handler(error: QueryPayloadError, _: &HttpRequest) -> actix_web::Error {
+    let api_error = ApiError::InvalidQuery(error.to_string());
+    let response = api_error.error_response();
+    InternalError::from_response(error, response).into()
+}
+
+/// Routes of the JSON API, mounted under `/api/v1`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::JsonConfig::default().error_handler(json_error_handler))
+        .app_data(web::QueryConfig::default().error_handler(query_error_handler))
+        .service(list_turtles)
+        .service(get_turtle)
+        .service(get_orders)
+        .service(set_orders)
+        .service(add_orders)
+        .service(cancel_order)
+        .service(get_telemetry)
+        .service(get_telemetry_history)
+        .service(get_infos)
+        .service(get_info)
+        .service(get_inventory)
+        .service(get_fuel)
+        .service(get_drifts)
+        .service(list_mining_plots)
+        .service(get_plot_yields)
+        .service(set_plot_turtle)
+        .service(list_mining_areas)
+        .service(get_mining_area)
+        .service(set_mining_area)
+        .service(set_turtle_area)
+        .service(list_stations)
+        .service(set_station)
+        .service(delete_station)
+        .service(list_blocks);
+}
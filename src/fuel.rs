@@ -0,0 +1,193 @@
+//! Fuel used by the commands sent to a turtle, so that it can always reach a refuel point.
+//!
+//! Every block moved costs 1 fuel, turning is free. The trip to the nearest refuel point is
+//! estimated with the Manhattan distance, `world.fuel_reserve` covers the detours of the paths.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::WorldConfig,
+    stations::{Station, StationKind},
+    turtle::{Command, CommandName, Turtle},
+    utils::Position,
+};
+
+pub fn command_cost(command: &Command) -> i32 {
+    match command.name() {
+        CommandName::Forward
+        | CommandName::ForwardDig
+        | CommandName::ForwardDigUp
+        | CommandName::Up
+        | CommandName::Down => command.argument().max(0),
+        _ => 0,
+    }
+}
+
+pub fn fuel_cost(commands: &[Command]) -> i32 {
+    commands.iter().map(command_cost).sum()
+}
+
+/// Where a turtle low on fuel can go: the usable fuel stations, or its home without any.
+pub fn refuel_points(stations: &[Station], world: &WorldConfig) -> Vec<Position> {
+    let points: Vec<_> = stations
+        .iter()
+        .filter(|station| station.kind == StationKind::Fuel && station.is_usable())
+        .map(|station| station.position)
+        .collect();
+    if points.is_empty() {
+        vec![world.chest_position]
+    } else {
+        points
+    }
+}
+
+/// Estimated fuel needed to reach the nearest of the refuel points.
+pub fn return_cost(refuel_points: &[Position], from: Position) -> i32 {
+    refuel_points
+        .iter()
+        .map(|point| {
+            let diff = *point - from;
+            diff.x.abs() + diff.y.abs() + diff.z.abs()
+        })
+        .min()
+        .unwrap_or_default()
+}
+
+/// Fuel a turtle can spend on the commands planned for it.
+#[derive(Debug, Clone)]
+pub struct FuelBudget {
+    /// Fuel left once the commands planned so far are run.
+    pub level: i32,
+    /// Fuel kept on top of the trip to the nearest refuel point.
+    pub reserve: i32,
+    pub refuel_points: Vec<Position>,
+}
+
+impl FuelBudget {
+    fn can_return(&self, level: i32, from: Position) -> bool {
+        level >= return_cost(&self.refuel_points, from) + self.reserve
+    }
+
+    /// Whether the turtle can run every command from `start` and still reach a refuel point.
+    pub fn allows(&self, start: &Turtle, commands: &[Command]) -> bool {
+        self.truncate(start, commands) == commands
+    }
+
+    /// The commands the turtle can run from `start` while staying able to reach a refuel point
+    /// after each step, the move where it would not is cut short.
+    pub fn truncate(&self, start: &Turtle, commands: &[Command]) -> Vec<Command> {
+        let mut turtle = start.clone();
+        let mut level = self.level;
+        let mut result = Vec::new();
+        for command in commands {
+            if command_cost(command) == 0 {
+                turtle.apply(command, command.argument());
+                result.push(command.clone());
+                continue;
+            }
+            let mut steps = 0;
+            while steps < command.argument() {
+                let mut next = turtle.clone();
+                next.apply(command, steps + 1);
+                if !self.can_return(level - steps - 1, next.pos) {
+                    break;
+                }
+                steps += 1;
+            }
+            if steps > 0 {
+                result.push(Command::new(command.name().clone(), steps));
+            }
+            if steps < command.argument() {
+                break;
+            }
+            turtle.apply(command, steps);
+            level -= steps;
+        }
+        result
+    }
+
+    pub fn spend(&mut self, commands: &[Command]) {
+        self.level -= fuel_cost(commands);
+    }
+}
+
+/// Fuel a turtle is expected to have once it ran its pending batch and its queued orders.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FuelProjection {
+    /// Last fuel level reported by the turtle, `None` when unknown or unlimited.
+    pub level: Option<i32>,
+    /// Fuel used by the pending batch and the queued orders.
+    pub cost: i32,
+    /// Fuel left once they are run.
+    pub projected: Option<i32>,
+    /// Estimated fuel to reach the nearest refuel point from where the turtle ends.
+    pub return_cost: i32,
+    /// Queued orders only planned when sent, e.g. `MinePlot`, not counted in the cost.
+    pub unplanned: usize,
+}
+
+pub fn project(turtle: &Turtle, stations: &[Station], world: &WorldConfig) -> FuelProjection {
+    let level = turtle.telemetry.fuel_level;
+    let pending = turtle.batch.iter().flat_map(|batch| &batch.commands);
+    let (queued, unplanned): (Vec<_>, Vec<_>) = turtle
+        .queue
+        .commands()
+        .partition(|command| !matches!(command.name(), CommandName::Home | CommandName::MinePlot));
+    let mut end = turtle.clone();
+    let mut cost = 0;
+    for command in pending.chain(queued) {
+        end.apply(command, command.argument());
+        cost += command_cost(command);
+    }
+    FuelProjection {
+        level,
+        cost,
+        projected: level.map(|level| level - cost),
+        return_cost: return_cost(&refuel_points(stations, world), end.pos),
+        unplanned: unplanned.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fuel_cost, return_cost, FuelBudget};
+    use crate::{
+        config::WorldConfig,
+        turtle::{Command, CommandName, Turtle},
+        utils::{Direction, Position},
+    };
+
+    #[test]
+    fn test_truncate() {
+        let home = Position { x: 0, y: 0, z: 0 };
+        let world = WorldConfig {
+            chest_position: home,
+            ..WorldConfig::default()
+        };
+        let mut turtle = Turtle::default("test".to_string(), &world);
+        turtle.direction = Direction::East;
+        let commands = vec![
+            Command::new(CommandName::Forward, 3),
+            Command::new(CommandName::Left, 1),
+            Command::new(CommandName::Down, 10),
+        ];
+        assert_eq!(fuel_cost(&commands), 13);
+        let budget = FuelBudget {
+            level: 20,
+            reserve: 2,
+            refuel_points: vec![home],
+        };
+        // 9 blocks away with 11 fuel left, the reserve included
+        assert_eq!(
+            budget.truncate(&turtle, &commands),
+            [
+                Command::new(CommandName::Forward, 3),
+                Command::new(CommandName::Left, 1),
+                Command::new(CommandName::Down, 6),
+            ]
+        );
+        assert!(!budget.allows(&turtle, &commands));
+        assert!(budget.allows(&turtle, &commands[..2]));
+        assert_eq!(return_cost(&[home], Position { x: 3, y: -2, z: 1 }), 6);
+    }
+}
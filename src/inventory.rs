@@ -0,0 +1,119 @@
+//! What a turtle carries, as reported by the turtle at the start of each loop.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::WorldConfig;
+
+pub const INVENTORY_SIZE: usize = 16;
+/// Slot holding the fuel, `16` in lua. Never emptied in the chests.
+pub const FUEL_SLOT: usize = INVENTORY_SIZE - 1;
+pub const STACK_SIZE: u32 = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub name: String,
+    pub count: u32,
+}
+
+/// The 16 slots of a turtle, `None` for the empty ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Inventory {
+    pub slots: Vec<Option<ItemStack>>,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Inventory {
+            slots: vec![None; INVENTORY_SIZE],
+        }
+    }
+}
+
+impl Inventory {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.slots.len() != INVENTORY_SIZE {
+            return Err(format!(
+                "expected {} slots, got {}",
+                INVENTORY_SIZE,
+                self.slots.len()
+            ));
+        }
+        for (index, stack) in self.slots.iter().enumerate() {
+            if let Some(stack) = stack.as_ref().filter(|stack| {
+                stack.name.is_empty() || stack.count == 0 || stack.count > STACK_SIZE
+            }) {
+                return Err(format!("invalid stack in slot {}: {:?}", index + 1, stack));
+            }
+        }
+        Ok(())
+    }
+
+    /// Empty slots, the fuel slot aside.
+    pub fn free_slots(&self) -> usize {
+        self.slots
+            .iter()
+            .take(FUEL_SLOT)
+            .filter(|slot| slot.is_none())
+            .count()
+    }
+
+    /// Number of items of a kind, the fuel slot aside.
+    pub fn count(&self, name: &str) -> u32 {
+        self.slots
+            .iter()
+            .take(FUEL_SLOT)
+            .flatten()
+            .filter(|stack| stack.name == name)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    /// Whether the turtle should go and empty its slots: too few of them are free, or it carries
+    /// one of the `valuable_items`.
+    pub fn should_deposit(&self, world: &WorldConfig) -> bool {
+        self.free_slots() <= world.deposit_free_slots as usize
+            || world
+                .valuable_items
+                .iter()
+                .any(|valuable| self.count(valuable) > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Inventory, ItemStack, FUEL_SLOT};
+    use crate::config::WorldConfig;
+
+    fn stack(name: &str, count: u32) -> Option<ItemStack> {
+        Some(ItemStack {
+            name: name.to_string(),
+            count,
+        })
+    }
+
+    #[test]
+    fn test_should_deposit() {
+        let world = WorldConfig {
+            deposit_free_slots: 2,
+            valuable_items: vec!["minecraft:diamond".to_string()],
+            ..WorldConfig::default()
+        };
+        let mut inventory = Inventory::default();
+        inventory.slots[FUEL_SLOT] = stack("minecraft:coal", 12);
+        for slot in 0..12 {
+            inventory.slots[slot] = stack("minecraft:cobblestone", 64);
+        }
+        assert_eq!(inventory.free_slots(), 3);
+        assert!(!inventory.should_deposit(&world));
+        inventory.slots[12] = stack("minecraft:diamond", 1);
+        assert!(inventory.should_deposit(&world));
+        inventory.slots[12] = stack("minecraft:dirt", 1);
+        assert!(inventory.should_deposit(&world));
+        assert!(inventory.validate().is_ok());
+
+        inventory.slots[0] = stack("minecraft:dirt", 0);
+        assert!(inventory.validate().is_err());
+        inventory.slots.pop();
+        assert!(inventory.validate().is_err());
+    }
+}
@@ -0,0 +1,222 @@
+//! Where the mining plots are placed.
+//!
+//! The plots are cells of a grid on the X/Z plane, the cell `(0, 0)` being the plot at
+//! `world.mining_position`. The grid spreads ahead and to the right of `world.plot_direction`.
+//! The n-th plot created goes to the n-th cell of the layout that doesn't overlap an excluded or
+//! protected area, changing the layout of a world moves the next plots.
+
+use serde::{Deserialize, Serialize};
+use std::iter;
+use strum_macros::{Display, EnumString};
+
+use crate::{
+    config::WorldConfig,
+    utils::{Area, Position},
+};
+
+/// Order in which the cells of the grid are given to the plots.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString, Serialize, Deserialize,
+)]
+pub enum PlotLayout {
+    /// Rows of `world.plots_wide` plots going right, the next row ahead of the previous one.
+    #[default]
+    Rectangular,
+    /// Rings around the first plot, keeping the plots close to it.
+    Spiral,
+}
+
+/// Cells of the grid in the order of the layout, as `(right, ahead)` numbers of plots.
+fn cells(layout: PlotLayout, plots_wide: i32) -> Box<dyn Iterator<Item = (i32, i32)>> {
+    match layout {
+        PlotLayout::Rectangular => {
+            let plots_wide = plots_wide.max(1);
+            Box::new((0..).map(move |index| (index % plots_wide, index / plots_wide)))
+        }
+        PlotLayout::Spiral => {
+            // Right 1, ahead 1, left 2, behind 2, right 3...
+            let moves = (0..).flat_map(|leg: usize| {
+                let direction = match leg % 4 {
+                    0 => (1, 0),
+                    1 => (0, 1),
+                    2 => (-1, 0),
+                    _ => (0, -1),
+                };
+                iter::repeat_n(direction, leg / 2 + 1)
+            });
+            let cells = moves.scan((0, 0), |cell: &mut (i32, i32), (right, ahead)| {
+                *cell = (cell.0 + right, cell.1 + ahead);
+                Some(*cell)
+            });
+            Box::new(iter::once((0, 0)).chain(cells))
+        }
+    }
+}
+
+/// Unit vectors to the right and ahead of the plots.
+fn axes(world: &WorldConfig) -> (Position, Position) {
+    let direction = world.plot_direction;
+    (
+        direction.turn_right(1).forward_vector(),
+        direction.forward_vector(),
+    )
+}
+
+/// Corner of the plot in a cell, where the mining of its first segment starts.
+fn cell_position((right, ahead): (i32, i32), world: &WorldConfig) -> Position {
+    let (right_vector, ahead_vector) = axes(world);
+    world.mining_position + (right_vector * right + ahead_vector * ahead) * world.plot_size
+}
+
+/// Blocks a plot may dig, down to the last segment.
+pub fn plot_area(position: Position, world: &WorldConfig) -> Area {
+    let (right, ahead) = axes(world);
+    let far = position + (right + ahead) * (world.plot_size - 1);
+    Area::new(
+        position + Position { x: 0, y: 1, z: 0 },
+        Position {
+            y: world.min_mining_y - 1,
+            ..far
+        },
+    )
+}
+
+/// Whether a plot at `position` would dig in an excluded or protected area.
+fn is_excluded(position: Position, world: &WorldConfig) -> bool {
+    let area = plot_area(position, world);
+    world
+        .excluded_areas
+        .iter()
+        .chain(&world.protected_areas)
+        .any(|excluded| excluded.intersects(&area))
+}
+
+/// Corner of the plot with this id, `None` once `world.max_plots` plots were created.
+pub fn plot_position(id: u32, world: &WorldConfig) -> Option<Position> {
+    if world.max_plots.is_some_and(|max_plots| id >= max_plots) {
+        return None;
+    }
+    cells(world.plot_layout, world.plots_wide)
+        .map(|cell| cell_position(cell, world))
+        .filter(|position| !is_excluded(*position, world))
+        .nth(id as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plot_area, plot_position, PlotLayout};
+    use crate::{
+        config::WorldConfig,
+        utils::{Area, Direction, Position},
+    };
+    use std::collections::HashSet;
+
+    fn world(layout: PlotLayout) -> WorldConfig {
+        WorldConfig {
+            mining_position: Position { x: 0, y: 40, z: 0 },
+            plots_wide: 3,
+            plot_size: 4,
+            plot_layout: layout,
+            ..WorldConfig::default()
+        }
+    }
+
+    fn offset(id: u32, world: &WorldConfig) -> Position {
+        plot_position(id, world).unwrap() - world.mining_position
+    }
+
+    #[test]
+    fn test_rectangular_rows() {
+        let world = world(PlotLayout::Rectangular);
+        assert_eq!(offset(0, &world), Position { x: 0, y: 0, z: 0 });
+        assert_eq!(offset(2, &world), Position { x: 8, y: 0, z: 0 });
+        // The next row is north of the first one, at the same height
+        assert_eq!(offset(3, &world), Position { x: 0, y: 0, z: -4 });
+        assert_eq!(offset(7, &world), Position { x: 4, y: 0, z: -8 });
+    }
+
+    #[test]
+    fn test_plot_direction() {
+        let mut world = world(PlotLayout::Rectangular);
+        world.plot_direction = Direction::West;
+        // Rows going north, the next row west of the first one
+        assert_eq!(offset(1, &world), Position { x: 0, y: 0, z: -4 });
+        assert_eq!(offset(3, &world), Position { x: -4, y: 0, z: 0 });
+        let area = plot_area(world.mining_position, &world);
+        assert_eq!(
+            area.min(),
+            Position {
+                x: -3,
+                y: -33,
+                z: -3
+            }
+        );
+    }
+
+    #[test]
+    fn test_spiral() {
+        let world = world(PlotLayout::Spiral);
+        assert_eq!(offset(0, &world), Position { x: 0, y: 0, z: 0 });
+        assert_eq!(offset(1, &world), Position { x: 4, y: 0, z: 0 });
+        assert_eq!(offset(2, &world), Position { x: 4, y: 0, z: -4 });
+        // The first ring surrounds the first plot
+        let ring: HashSet<_> = (0..9).map(|id| offset(id, &world)).collect();
+        for x in [-4, 0, 4] {
+            for z in [-4, 0, 4] {
+                assert!(ring.contains(&Position { x, y: 0, z }), "{} {}", x, z);
+            }
+        }
+    }
+
+    #[test]
+    fn test_plots_never_overlap() {
+        for layout in [PlotLayout::Rectangular, PlotLayout::Spiral] {
+            let world = world(layout);
+            let areas: Vec<_> = (0..50)
+                .map(|id| plot_area(plot_position(id, &world).unwrap(), &world))
+                .collect();
+            for (index, area) in areas.iter().enumerate() {
+                assert!(areas[index + 1..]
+                    .iter()
+                    .all(|other| !area.intersects(other)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_excluded_areas() {
+        let mut world = world(PlotLayout::Rectangular);
+        // Overlaps the second plot at the bottom, and the fourth one through a protected area
+        world.excluded_areas = vec![Area::new(
+            Position {
+                x: 5,
+                y: -20,
+                z: -1,
+            },
+            Position {
+                x: 5,
+                y: -20,
+                z: -1,
+            },
+        )];
+        world.protected_areas = vec![Area::new(
+            Position { x: 0, y: 41, z: -4 },
+            Position {
+                x: -10,
+                y: 50,
+                z: -4,
+            },
+        )];
+        assert_eq!(offset(0, &world), Position { x: 0, y: 0, z: 0 });
+        assert_eq!(offset(1, &world), Position { x: 8, y: 0, z: 0 });
+        assert_eq!(offset(2, &world), Position { x: 4, y: 0, z: -4 });
+    }
+
+    #[test]
+    fn test_max_plots() {
+        let mut world = world(PlotLayout::Spiral);
+        world.max_plots = Some(4);
+        assert!(plot_position(3, &world).is_some());
+        assert_eq!(plot_position(4, &world), None);
+    }
+}
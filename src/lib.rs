@@ -0,0 +1,22 @@
+pub mod api;
+pub mod config;
+pub mod fuel;
+mod functions;
+pub mod inventory;
+pub mod layout;
+pub mod locks;
+pub mod mining_areas;
+pub mod mining_plots;
+pub mod mining_strategies;
+pub mod order_parser;
+pub mod order_queue;
+pub mod pathfinding;
+pub mod persistance;
+pub mod routes;
+pub mod script;
+pub mod simulator;
+pub mod stations;
+pub mod telemetry;
+pub mod turtle;
+pub mod utils;
+pub mod world_map;
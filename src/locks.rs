@@ -0,0 +1,35 @@
+//! Locks serializing the requests of each turtle, the requests of different turtles run
+//! concurrently.
+//!
+//! The handlers load a turtle, update it and save it back: two requests of the same turtle
+//! running at once would lose one of the updates.
+
+use futures::lock::Mutex;
+use std::{collections::HashMap, sync::Arc};
+
+/// One lock per turtle name, shared by every worker of the server.
+#[derive(Default)]
+pub struct TurtleLocks {
+    locks: std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl TurtleLocks {
+    /// The lock of a turtle, to hold while updating it.
+    pub fn turtle(&self, name: &str) -> Arc<Mutex<()>> {
+        self.locks
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// The locks of several turtles, each once and sorted by name: taken in this order, two
+    /// requests holding some of the same turtles can't wait on each other.
+    pub fn turtles<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> Vec<Arc<Mutex<()>>> {
+        let mut names: Vec<_> = names.into_iter().collect();
+        names.sort_unstable();
+        names.dedup();
+        names.into_iter().map(|name| self.turtle(name)).collect()
+    }
+}
@@ -0,0 +1,155 @@
+//! Named regions of the world where the turtles mine plots.
+//!
+//! The `default` area is described by the `[world]` config, the others are created through the
+//! API. Each turtle mines in one area, `default` unless assigned another.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::WorldConfig,
+    persistance::{Storage, StorageResult},
+    utils::{Direction, Position},
+};
+
+/// Name of the area described by the `[world]` config, holding the plots created before areas.
+pub const DEFAULT_AREA: &str = "default";
+
+pub fn default_area() -> String {
+    DEFAULT_AREA.to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MiningArea {
+    pub name: String,
+    /// Corner of the first plot, where the mining of its first segment starts.
+    pub origin: Position,
+    /// Width and length of the plots.
+    pub plot_size: i32,
+    /// Lowest layer mined, e.g. just above the bedrock.
+    pub min_y: i32,
+    /// Direction the plots spread from their corner, ahead and to the right of it.
+    pub direction: Direction,
+    /// Where the turtles mining the area drop off what they dug, between two chests.
+    pub chest_position: Position,
+}
+
+impl MiningArea {
+    /// The `default` area.
+    pub fn from_world(world: &WorldConfig) -> Self {
+        MiningArea {
+            name: default_area(),
+            origin: world.mining_position,
+            plot_size: world.plot_size,
+            min_y: world.min_mining_y,
+            direction: world.plot_direction,
+            chest_position: world.chest_position,
+        }
+    }
+
+    /// The world config with the values of the area, used to plan the mining of its plots.
+    pub fn world(&self, world: &WorldConfig) -> WorldConfig {
+        WorldConfig {
+            mining_position: self.origin,
+            plot_size: self.plot_size,
+            min_mining_y: self.min_y,
+            plot_direction: self.direction,
+            chest_position: self.chest_position,
+            ..world.clone()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("the name must not be empty".to_string());
+        }
+        if self.plot_size < 2 {
+            return Err(format!(
+                "plot_size must be at least 2, got {}",
+                self.plot_size
+            ));
+        }
+        if self.min_y >= self.origin.y {
+            return Err(format!(
+                "min_y ({}) must be below the origin ({})",
+                self.min_y, self.origin.y
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The world config of an area, the `[world]` config itself for `default` or an unknown area.
+pub async fn area_world(
+    storage: &dyn Storage,
+    world: &WorldConfig,
+    name: &str,
+) -> StorageResult<WorldConfig> {
+    if name == DEFAULT_AREA {
+        return Ok(world.clone());
+    }
+    match storage.find_area(name).await? {
+        Some(area) => Ok(area.world(world)),
+        None => {
+            log::warn!("Unknown mining area {}, using the default one", name);
+            Ok(world.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MiningArea, DEFAULT_AREA};
+    use crate::{
+        config::WorldConfig,
+        utils::{Direction, Position},
+    };
+
+    fn area() -> MiningArea {
+        MiningArea {
+            name: "south".to_string(),
+            origin: Position {
+                x: 100,
+                y: 40,
+                z: 200,
+            },
+            plot_size: 8,
+            min_y: -58,
+            direction: Direction::South,
+            chest_position: Position {
+                x: 100,
+                y: 64,
+                z: 190,
+            },
+        }
+    }
+
+    #[test]
+    fn test_area_world() {
+        let world = WorldConfig::default();
+        let default = MiningArea::from_world(&world);
+        assert_eq!(default.name, DEFAULT_AREA);
+        assert_eq!(default.world(&world).mining_position, world.mining_position);
+
+        let area_world = area().world(&world);
+        assert_eq!(area_world.mining_position, area().origin);
+        assert_eq!(area_world.plot_direction, Direction::South);
+        assert_eq!(area_world.chest_position, area().chest_position);
+        assert_eq!(area_world.max_depth_segment(), 98 / world.plot_depth);
+        assert_eq!(area_world.ores, world.ores);
+    }
+
+    #[test]
+    fn test_validate_area() {
+        assert!(area().validate().is_ok());
+        let too_deep = MiningArea {
+            min_y: 40,
+            ..area()
+        };
+        assert!(too_deep.validate().is_err());
+        let unnamed = MiningArea {
+            name: String::new(),
+            ..area()
+        };
+        assert!(unnamed.validate().is_err());
+    }
+}
@@ -1,76 +1,265 @@
 use crate::{
-    turtle::{Command, CommandName},
+    config::WorldConfig,
+    layout::plot_position,
+    mining_areas::default_area,
+    mining_strategies::{segment_positions, StrategyName},
+    persistance::{Storage, StorageResult},
+    turtle::Command,
     utils::{Direction, Position},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Blocks dug while mining a depth segment, by name.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct SegmentYield {
+    pub segment: u32,
+    pub blocks: HashMap<String, u32>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MiningPlot {
+    /// Order in which the plot was created.
+    #[serde(default)]
+    pub id: u32,
+    /// Mining area of the plot, the plots created before areas are in the default one.
+    #[serde(default = "default_area")]
+    pub area: String,
+    /// Order in which the plot was created in its area, giving its place in the layout.
+    #[serde(default)]
+    pub index: u32,
     pub position: Position,
     pub mined_depth_segment: u32,
+    /// Turtle mining the plot, a plot without one and not finished is resumed by the next turtle
+    /// looking for a plot.
     pub current_turtle: Option<String>,
+    /// Every segment was mined, or the next one is blocked.
+    #[serde(default)]
+    pub finished: bool,
+    /// How the segments are mined, the plots created before strategies were quarries.
+    #[serde(default)]
+    pub strategy: StrategyName,
+    #[serde(default)]
+    pub yields: Vec<SegmentYield>,
+    /// Positions next to the ores found, dug before the next segment to follow the veins.
+    #[serde(default)]
+    pub ore_targets: Vec<Position>,
+}
+
+/// The plot and depth segment a batch of commands is mining.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct MiningTask {
+    pub plot: u32,
+    pub segment: u32,
 }
 
 impl MiningPlot {
-    pub fn new(position: Position, turtle_name: &str) -> Self {
+    pub fn new(
+        id: u32,
+        area: &str,
+        index: u32,
+        position: Position,
+        turtle_name: &str,
+        strategy: StrategyName,
+    ) -> Self {
         MiningPlot {
+            id,
+            area: area.to_string(),
+            index,
             position,
             mined_depth_segment: 0,
             current_turtle: Some(turtle_name.to_string()),
+            finished: false,
+            strategy,
+            yields: Vec::new(),
+            ore_targets: Vec::new(),
         }
     }
-}
 
-const PLOTS_WIDE: i32 = 6;
-pub const PLOT_SIZE: i32 = 6;
-pub const PLOT_DEPTH: u32 = 3;
-pub const PLOT_MAX_DEPTH_SEGMENT: u32 =
-    ((IN_WORLD_MINING_POSITION.y + 32) / PLOT_DEPTH as i32) as u32;
-pub const PLOT_DIRECTION: Direction = Direction::North;
-
-const IN_WORLD_MINING_POSITION: Position = Position {
-    x: -559,
-    y: 48,
-    z: -2777,
-};
+    /// Create the next plot of `area` for `turtle_name`, at its place in the layout of `world`.
+    /// `None` when the layout is full.
+    pub async fn create(
+        storage: &dyn Storage,
+        area: &str,
+        world: &WorldConfig,
+        turtle_name: &str,
+        strategy: StrategyName,
+    ) -> StorageResult<Option<Self>> {
+        storage
+            .create_plot(area, &|id, index| {
+                plot_position(index, world).map(|position| {
+                    MiningPlot::new(id, area, index, position, turtle_name, strategy)
+                })
+            })
+            .await
+    }
 
-#[cfg(test)]
-mod tests {
-    // use crate::utils::Position;
+    pub fn task(&self) -> MiningTask {
+        MiningTask {
+            plot: self.id,
+            segment: self.mined_depth_segment,
+        }
+    }
 
-    // use super::mining_orders;
+    /// Corner where the mining of a segment starts, facing `world.plot_direction`.
+    pub fn segment_start(&self, segment: u32, world: &WorldConfig) -> Position {
+        Position {
+            y: self.position.y - (segment * world.plot_depth) as i32,
+            ..self.position
+        }
+    }
 
-    #[test]
-    fn test_mining_orders() {
-        // let result = mining_orders(
-        //     &mut Position { x: 0, y: 0, z: 0 },
-        //     &mut crate::utils::Direction::East,
-        // );
-        // println!("{:#?}", result);
+    pub fn segment_orders(&self, world: &WorldConfig) -> Vec<Command> {
+        self.strategy.strategy(world).segment_orders(world)
+    }
+
+    /// Positions dug when mining a segment.
+    pub fn segment_positions(&self, segment: u32, world: &WorldConfig) -> HashSet<Position> {
+        let start = self.segment_start(segment, world);
+        segment_positions(self.strategy.strategy(world).as_ref(), world)
+            .into_iter()
+            .map(|position| position + start)
+            .collect()
     }
-}
 
-pub fn mining_orders() -> Vec<Command> {
-    let mut result = Vec::new();
-    for turn in 1..PLOT_SIZE {
-        let side = if turn % 2 == 0 {
-            CommandName::Left
-        } else {
-            CommandName::Right
+    /// Positions dug by the segments mined so far, the current one included.
+    pub fn mined_positions(&self, world: &WorldConfig) -> HashSet<Position> {
+        (0..=self.mined_depth_segment)
+            .flat_map(|segment| self.segment_positions(segment, world))
+            .collect()
+    }
+
+    pub fn add_yields(&mut self, segment: u32, blocks: &HashMap<String, u32>) {
+        let index = match self.yields.iter().position(|item| item.segment == segment) {
+            Some(index) => index,
+            None => {
+                self.yields.push(SegmentYield {
+                    segment,
+                    ..SegmentYield::default()
+                });
+                self.yields.len() - 1
+            }
         };
-        result.append(&mut vec![
-            Command::new(CommandName::ForwardDig, PLOT_SIZE - 1),
-            Command::new(side.clone(), 1),
-            Command::new(CommandName::ForwardDig, 1),
-            Command::new(side, 1),
-        ]);
+        for (name, count) in blocks {
+            *self.yields[index].blocks.entry(name.clone()).or_default() += count;
+        }
+    }
+
+    /// Blocks dug on every segment of the plot.
+    pub fn total_yields(&self) -> HashMap<String, u32> {
+        let mut total = HashMap::new();
+        for segment in &self.yields {
+            for (name, count) in &segment.blocks {
+                *total.entry(name.clone()).or_default() += count;
+            }
+        }
+        total
+    }
+
+    /// Record an ore dug at `position`: its neighbours that may still hold ore become targets.
+    /// `explored` tells the positions already known to be dug.
+    pub fn add_ore(
+        &mut self,
+        position: Position,
+        explored: impl Fn(&Position) -> bool,
+        world: &WorldConfig,
+    ) {
+        self.ore_targets.retain(|target| *target != position);
+        let mined = self.mined_positions(world);
+        for direction in [Direction::North, Direction::East] {
+            for offset in [direction.forward_vector(), direction.forward_vector() * -1] {
+                self.add_ore_target(position + offset, &mined, &explored);
+            }
+        }
+        for y in [1, -1] {
+            let neighbour = Position {
+                y: position.y + y,
+                ..position
+            };
+            self.add_ore_target(neighbour, &mined, &explored);
+        }
+    }
+
+    fn add_ore_target(
+        &mut self,
+        target: Position,
+        mined: &HashSet<Position>,
+        explored: &impl Fn(&Position) -> bool,
+    ) {
+        if !mined.contains(&target) && !explored(&target) && !self.ore_targets.contains(&target) {
+            self.ore_targets.push(target);
+        }
     }
-    result.push(Command::new(CommandName::ForwardDig, PLOT_SIZE - 1));
-    result
 }
 
-pub fn new_mining_position(total_plots: i32) -> Position {
-    let x = (total_plots % PLOTS_WIDE) as i32 * PLOT_SIZE;
-    let y = (total_plots / PLOTS_WIDE) as i32 * PLOT_SIZE;
-    Position { x, y, z: 0 } + IN_WORLD_MINING_POSITION
+#[cfg(test)]
+mod tests {
+    use super::MiningPlot;
+    use crate::{
+        config::WorldConfig, mining_areas::DEFAULT_AREA, mining_strategies::StrategyName,
+        utils::Position,
+    };
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_ore_targets() {
+        let world = WorldConfig::default();
+        let mut plot = MiningPlot::new(
+            0,
+            DEFAULT_AREA,
+            0,
+            world.mining_position,
+            "test",
+            StrategyName::Quarry,
+        );
+        let corner = world.mining_position;
+        // An ore on the edge of the plot, only the neighbours outside are targets
+        plot.add_ore(corner, |_| false, &world);
+        let west = Position {
+            x: corner.x - 1,
+            ..corner
+        };
+        let south = Position {
+            z: corner.z + 1,
+            ..corner
+        };
+        let above_west = Position {
+            y: corner.y + 1,
+            ..west
+        };
+        assert_eq!(plot.ore_targets.len(), 2);
+        assert!(plot.ore_targets.contains(&west));
+        assert!(plot.ore_targets.contains(&south));
+
+        // Digging a target removes it, the explored positions are not targeted again
+        plot.add_ore(west, |position| *position == above_west, &world);
+        assert!(!plot.ore_targets.contains(&west));
+        assert_eq!(plot.ore_targets.len(), 5);
+        assert!(!plot.ore_targets.contains(&above_west));
+    }
+
+    #[test]
+    fn test_yields() {
+        let mut plot = MiningPlot::new(
+            0,
+            DEFAULT_AREA,
+            0,
+            Position { x: 0, y: 0, z: 0 },
+            "test",
+            StrategyName::Quarry,
+        );
+        let stone = HashMap::from([("minecraft:stone".to_string(), 10)]);
+        let ores = HashMap::from([
+            ("minecraft:stone".to_string(), 2),
+            ("minecraft:iron_ore".to_string(), 3),
+        ]);
+        plot.add_yields(0, &stone);
+        plot.add_yields(0, &ores);
+        plot.add_yields(1, &stone);
+        assert_eq!(plot.yields.len(), 2);
+        assert_eq!(plot.yields[0].blocks["minecraft:stone"], 12);
+        let total = plot.total_yields();
+        assert_eq!(total["minecraft:stone"], 22);
+        assert_eq!(total["minecraft:iron_ore"], 3);
+    }
 }
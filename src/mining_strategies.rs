@@ -0,0 +1,279 @@
+//! How a depth segment of a plot is mined.
+//!
+//! Every strategy starts at the corner of the segment facing `world.plot_direction`, the plot
+//! spreading `plot_size` blocks ahead and to the right of it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use strum_macros::{Display, EnumString};
+
+use crate::{
+    config::WorldConfig,
+    turtle::{Command, CommandName},
+    utils::{Direction, Position},
+};
+
+/// Produces the commands mining one depth segment of a plot.
+pub trait MiningStrategy {
+    fn segment_orders(&self, world: &WorldConfig) -> Vec<Command>;
+}
+
+/// Strip the whole square, three layers at a time, going back and forth.
+pub struct Quarry;
+
+/// A 1x2 tunnel along the west side of the plot, with a branch going east every `spacing`
+/// blocks. Leaves most of the stone in place, the ores next to the tunnels are found by the veins.
+pub struct BranchMining {
+    pub spacing: i32,
+}
+
+/// Strip the whole square, three layers at a time, spiraling inwards.
+pub struct SpiralQuarry;
+
+/// Append `command`, merging it with the last one when they have the same name.
+fn push(result: &mut Vec<Command>, command: Command) {
+    if command.argument() <= 0 {
+        return;
+    }
+    match result.last_mut() {
+        Some(last) if last.name() == command.name() => {
+            *last = Command::new(command.name().clone(), last.argument() + command.argument())
+        }
+        _ => result.push(command),
+    }
+}
+
+impl MiningStrategy for Quarry {
+    fn segment_orders(&self, world: &WorldConfig) -> Vec<Command> {
+        let mut result = Vec::new();
+        for turn in 1..world.plot_size {
+            let side = if turn % 2 == 0 {
+                CommandName::Left
+            } else {
+                CommandName::Right
+            };
+            result.append(&mut vec![
+                Command::new(CommandName::ForwardDig, world.plot_size - 1),
+                Command::new(side.clone(), 1),
+                Command::new(CommandName::ForwardDig, 1),
+                Command::new(side, 1),
+            ]);
+        }
+        result.push(Command::new(CommandName::ForwardDig, world.plot_size - 1));
+        result
+    }
+}
+
+impl MiningStrategy for BranchMining {
+    fn segment_orders(&self, world: &WorldConfig) -> Vec<Command> {
+        let length = world.plot_size - 1;
+        let mut result = Vec::new();
+        for along in 0..world.plot_size {
+            if along % self.spacing.max(1) == 0 {
+                // Dig the branch and walk back to the main tunnel
+                push(&mut result, Command::new(CommandName::Right, 1));
+                push(&mut result, Command::new(CommandName::ForwardDigUp, length));
+                push(&mut result, Command::new(CommandName::Left, 2));
+                push(&mut result, Command::new(CommandName::Forward, length));
+                push(&mut result, Command::new(CommandName::Right, 1));
+            }
+            if along < length {
+                push(&mut result, Command::new(CommandName::ForwardDigUp, 1));
+            }
+        }
+        result
+    }
+}
+
+impl MiningStrategy for SpiralQuarry {
+    fn segment_orders(&self, world: &WorldConfig) -> Vec<Command> {
+        let size = world.plot_size;
+        // Three sides of the full length, then two of each shorter length down to 1
+        let mut lengths = vec![size - 1; 3];
+        for length in (1..size - 1).rev() {
+            lengths.extend([length, length]);
+        }
+        let mut result = Vec::new();
+        for (index, length) in lengths.into_iter().enumerate() {
+            if index > 0 {
+                result.push(Command::new(CommandName::Right, 1));
+            }
+            result.push(Command::new(CommandName::ForwardDig, length));
+        }
+        result
+    }
+}
+
+/// Name of a strategy, kept by the plots and given as the argument of `MinePlot`.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString, Serialize, Deserialize,
+)]
+pub enum StrategyName {
+    #[default]
+    Quarry,
+    BranchMining,
+    SpiralQuarry,
+}
+
+impl StrategyName {
+    pub const ALL: [StrategyName; 3] = [
+        StrategyName::Quarry,
+        StrategyName::BranchMining,
+        StrategyName::SpiralQuarry,
+    ];
+
+    /// Argument of the `MinePlot` command selecting the strategy, `0` keeps the current one. The
+    /// orders may also name the strategy, see [`crate::order_parser::parse_strategy`].
+    pub fn argument(self) -> i32 {
+        match self {
+            StrategyName::Quarry => 1,
+            StrategyName::BranchMining => 2,
+            StrategyName::SpiralQuarry => 3,
+        }
+    }
+
+    pub fn from_argument(argument: i32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|name| name.argument() == argument)
+    }
+
+    pub fn strategy(self, world: &WorldConfig) -> Box<dyn MiningStrategy> {
+        match self {
+            StrategyName::Quarry => Box::new(Quarry),
+            StrategyName::BranchMining => Box::new(BranchMining {
+                spacing: world.branch_spacing,
+            }),
+            StrategyName::SpiralQuarry => Box::new(SpiralQuarry),
+        }
+    }
+}
+
+/// Positions dug by the commands, run from `start` facing `direction`. The start is included,
+/// the turtle went there.
+pub fn dug_positions(
+    start: Position,
+    direction: Direction,
+    commands: &[Command],
+) -> HashSet<Position> {
+    let up = Position { x: 0, y: 1, z: 0 };
+    let mut position = start;
+    let mut direction = direction;
+    let mut dug = HashSet::from([start]);
+    for command in commands {
+        for _ in 0..command.argument() {
+            match command.name() {
+                CommandName::ForwardDig => {
+                    dug.insert(position + up);
+                    dug.insert(position - up);
+                }
+                CommandName::ForwardDigUp => {
+                    dug.insert(position + up);
+                }
+                _ => {}
+            }
+            match command.name() {
+                CommandName::Forward | CommandName::ForwardDig | CommandName::ForwardDigUp => {
+                    position = position + direction.forward_vector()
+                }
+                CommandName::Up => position = position + up,
+                CommandName::Down => position = position - up,
+                CommandName::Left => direction = direction.turn_left(1),
+                CommandName::Right => direction = direction.turn_right(1),
+                _ => {}
+            }
+            dug.insert(position);
+        }
+    }
+    dug
+}
+
+/// Positions dug by a strategy from the corner of a segment at the origin.
+pub fn segment_positions(strategy: &dyn MiningStrategy, world: &WorldConfig) -> HashSet<Position> {
+    let origin = Position { x: 0, y: 0, z: 0 };
+    dug_positions(
+        origin,
+        world.plot_direction,
+        &strategy.segment_orders(world),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{segment_positions, StrategyName};
+    use crate::{config::WorldConfig, utils::Position};
+
+    fn world() -> WorldConfig {
+        WorldConfig {
+            plot_size: 7,
+            branch_spacing: 3,
+            ..WorldConfig::default()
+        }
+    }
+
+    /// Every position of the plot at the level of the turtle.
+    fn layer(world: &WorldConfig) -> Vec<Position> {
+        let mut positions = Vec::new();
+        for x in 0..world.plot_size {
+            for z in 0..world.plot_size {
+                positions.push(Position { x, y: 0, z: -z });
+            }
+        }
+        positions
+    }
+
+    #[test]
+    fn test_strategies_stay_in_plot() {
+        let world = world();
+        for name in StrategyName::ALL {
+            for position in segment_positions(name.strategy(&world).as_ref(), &world) {
+                assert!(
+                    (0..world.plot_size).contains(&position.x)
+                        && (-1..=1).contains(&position.y)
+                        && (0..world.plot_size).contains(&-position.z),
+                    "{} digs {:?}",
+                    name,
+                    position
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_quarries_strip_the_layer() {
+        let world = world();
+        for name in [StrategyName::Quarry, StrategyName::SpiralQuarry] {
+            let dug = segment_positions(name.strategy(&world).as_ref(), &world);
+            for position in layer(&world) {
+                assert!(dug.contains(&position), "{} skips {:?}", name, position);
+            }
+        }
+    }
+
+    #[test]
+    fn test_branch_mining() {
+        let world = world();
+        let dug = segment_positions(StrategyName::BranchMining.strategy(&world).as_ref(), &world);
+        for position in layer(&world) {
+            let in_tunnel = position.x == 0 || -position.z % world.branch_spacing == 0;
+            assert_eq!(dug.contains(&position), in_tunnel, "{:?}", position);
+        }
+        // Two blocks high, except at the far end of each tunnel
+        assert!(dug.contains(&Position { x: 0, y: 1, z: -3 }));
+        assert!(dug.contains(&Position { x: 5, y: 1, z: -6 }));
+        assert!(!dug.contains(&Position { x: 6, y: 1, z: -6 }));
+        assert!(dug.iter().all(|position| position.y >= 0));
+    }
+
+    #[test]
+    fn test_strategy_arguments() {
+        for name in StrategyName::ALL {
+            assert_eq!(StrategyName::from_argument(name.argument()), Some(name));
+        }
+        assert_eq!(StrategyName::from_argument(0), None);
+        assert_eq!(
+            "BranchMining".parse::<StrategyName>(),
+            Ok(StrategyName::BranchMining)
+        );
+    }
+}
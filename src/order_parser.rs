@@ -0,0 +1,265 @@
+//! Order language accepted by `POST /order/{name}`.
+//!
+//! One order per line, written `Name` or `Name,argument`:
+//!
+//! ```text
+//! # Go mine a bit, then come back
+//! Forward,3
+//! Left
+//! MinePlot
+//! Home
+//! ```
+//!
+//! Blank lines are ignored and `#` starts a comment running to the end of the line.
+//! When the argument is omitted, the default of the command is used (see
+//! [`CommandName::default_argument`]). `MinePlot` also takes the name of a strategy instead of
+//! its number, e.g. `MinePlot,BranchMining`.
+
+use crate::{
+    mining_strategies::StrategyName,
+    turtle::{Command, CommandName},
+};
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+const COMMENT_CHAR: char = '#';
+const ARGUMENT_SEPARATOR: char = ',';
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+/// Every problem found in an order list, in the order they appear.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseErrors(pub Vec<ParseError>);
+
+impl fmt::Display for ParseErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self.0.iter().map(ToString::to_string).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::error::Error for ParseErrors {}
+
+/// A trimmed piece of a line, with the 1-based column it starts at.
+struct Token<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+/// Split `code` on `,`, keeping track of the column of every field.
+fn tokenize(code: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let separators = code
+        .match_indices(ARGUMENT_SEPARATOR)
+        .map(|(index, _)| index)
+        .chain(std::iter::once(code.len()));
+    for end in separators {
+        let raw = &code[start..end];
+        let leading = raw.len() - raw.trim_start().len();
+        tokens.push(Token {
+            text: raw.trim(),
+            column: code[..start + leading].chars().count() + 1,
+        });
+        start = end + ARGUMENT_SEPARATOR.len_utf8();
+    }
+    tokens
+}
+
+fn parse_line(line: &str, line_number: usize) -> Result<Option<Command>, ParseError> {
+    let code = match line.find(COMMENT_CHAR) {
+        Some(index) => &line[..index],
+        None => line,
+    };
+    if code.trim().is_empty() {
+        return Ok(None);
+    }
+    let error = |column: usize, message: String| ParseError {
+        line: line_number,
+        column,
+        message,
+    };
+
+    let tokens = tokenize(code);
+    let name = &tokens[0];
+    if let Some(extra) = tokens.get(2) {
+        return Err(error(
+            extra.column,
+            format!(
+                "unexpected argument `{}`, `{}` takes at most one",
+                extra.text, name.text
+            ),
+        ));
+    }
+    let command_name = CommandName::from_str(name.text)
+        .map_err(|_| error(name.column, format!("unknown command `{}`", name.text)))?;
+    let argument = match tokens.get(1) {
+        Some(argument) if argument.text.is_empty() => {
+            return Err(error(argument.column, "missing argument after `,`".into()))
+        }
+        Some(argument) if command_name == CommandName::MinePlot => {
+            match argument.text.parse::<i32>() {
+                Ok(value) => check_strategy_argument(value),
+                Err(_) => parse_strategy(argument.text).map(StrategyName::argument),
+            }
+            .map_err(|message| error(argument.column, message))?
+        }
+        Some(argument) => {
+            let value = argument.text.parse::<i32>().map_err(|_| {
+                error(
+                    argument.column,
+                    format!("invalid argument `{}`, expected a number", argument.text),
+                )
+            })?;
+            if value < 0 {
+                return Err(error(
+                    argument.column,
+                    format!("invalid argument `{}`, must not be negative", value),
+                ));
+            }
+            value
+        }
+        None => command_name.default_argument(),
+    };
+    Ok(Some(Command::new(command_name, argument)))
+}
+
+/// The strategy named by the argument of `MinePlot`.
+pub fn parse_strategy(name: &str) -> Result<StrategyName, String> {
+    StrategyName::from_str(name).map_err(|_| {
+        let names: Vec<_> = StrategyName::ALL.iter().map(ToString::to_string).collect();
+        format!(
+            "unknown mining strategy `{}`, expected one of {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+/// Check the number of a strategy given to `MinePlot`, `0` keeps the current strategy.
+pub fn check_strategy_argument(value: i32) -> Result<i32, String> {
+    if value == 0 || StrategyName::from_argument(value).is_some() {
+        return Ok(value);
+    }
+    let numbers: Vec<_> = StrategyName::ALL
+        .iter()
+        .map(|name| format!("{} ({})", name.argument(), name))
+        .collect();
+    Err(format!(
+        "unknown mining strategy `{}`, expected 0 to keep the current one or one of {}",
+        value,
+        numbers.join(", ")
+    ))
+}
+
+/// Parse a list of orders, reporting every invalid line instead of stopping at the first one.
+pub fn parse_orders(input: &str) -> Result<Vec<Command>, ParseErrors> {
+    let mut orders = Vec::new();
+    let mut errors = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        match parse_line(line, index + 1) {
+            Ok(Some(order)) => orders.push(order),
+            Ok(None) => {}
+            Err(error) => errors.push(error),
+        }
+    }
+    if errors.is_empty() {
+        Ok(orders)
+    } else {
+        Err(ParseErrors(errors))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_orders, ParseError};
+    use crate::turtle::{Command, CommandName};
+
+    #[test]
+    fn test_parse_orders() {
+        let orders = parse_orders("Forward,3\nLeft,1\n\n  Down , 4  \nHome").unwrap();
+        assert_eq!(
+            orders,
+            vec![
+                Command::new(CommandName::Forward, 3),
+                Command::new(CommandName::Left, 1),
+                Command::new(CommandName::Down, 4),
+                Command::new(CommandName::Home, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_orders_comments_and_defaults() {
+        let orders =
+            parse_orders("# go home\nHome # no argument needed\nSleep\r\nForward").unwrap();
+        assert_eq!(
+            orders,
+            vec![
+                Command::new(CommandName::Home, 0),
+                Command::new(CommandName::Sleep, 2),
+                Command::new(CommandName::Forward, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_mining_strategy() {
+        let orders = parse_orders(
+            "MinePlot\nMinePlot,BranchMining\nMinePlot,SpiralQuarry\nMinePlot,2\nMinePlot,0",
+        )
+        .unwrap();
+        assert_eq!(
+            orders,
+            vec![
+                Command::new(CommandName::MinePlot, 0),
+                Command::new(CommandName::MinePlot, 2),
+                Command::new(CommandName::MinePlot, 3),
+                Command::new(CommandName::MinePlot, 2),
+                Command::new(CommandName::MinePlot, 0),
+            ]
+        );
+        let errors = parse_orders("MinePlot,Tunnel\nMinePlot,7").unwrap_err().0;
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].message,
+            "unknown mining strategy `Tunnel`, expected one of Quarry, BranchMining, SpiralQuarry"
+        );
+        assert_eq!(
+            errors[1].message,
+            "unknown mining strategy `7`, expected 0 to keep the current one or one of \
+             1 (Quarry), 2 (BranchMining), 3 (SpiralQuarry)"
+        );
+    }
+
+    #[test]
+    fn test_parse_orders_reports_every_error() {
+        let errors = parse_orders("Forwrd,3\nForward,\nUp,two\nDown,-1\nLeft,1,2\nRight,1")
+            .unwrap_err()
+            .0;
+        let positions: Vec<(usize, usize)> = errors
+            .iter()
+            .map(|ParseError { line, column, .. }| (*line, *column))
+            .collect();
+        assert_eq!(positions, vec![(1, 1), (2, 9), (3, 4), (4, 6), (5, 8)]);
+        assert_eq!(
+            errors[0].to_string(),
+            "line 1, column 1: unknown command `Forwrd`"
+        );
+    }
+}
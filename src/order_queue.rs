@@ -0,0 +1,162 @@
+//! Orders waiting to be sent to a turtle, kept until they are planned in a batch.
+//!
+//! The orders run by priority, then in the order they were queued. Each order gets an id to cancel
+//! it while it waits. When the queue is empty the turtle runs `world.idle_orders`.
+
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::turtle::Command;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Display,
+    EnumString,
+    Serialize,
+    Deserialize,
+)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedOrder {
+    pub id: u32,
+    pub priority: Priority,
+    pub command: Command,
+}
+
+/// The queued orders of a turtle, in the order they run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OrderQueue {
+    orders: Vec<QueuedOrder>,
+    last_id: u32,
+}
+
+impl OrderQueue {
+    pub fn orders(&self) -> &[QueuedOrder] {
+        &self.orders
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.orders.iter().map(|order| &order.command)
+    }
+
+    fn insert(
+        &mut self,
+        index: usize,
+        commands: Vec<Command>,
+        priority: Priority,
+    ) -> Vec<QueuedOrder> {
+        let mut added = Vec::new();
+        for command in commands {
+            self.last_id += 1;
+            added.push(QueuedOrder {
+                id: self.last_id,
+                priority,
+                command,
+            });
+        }
+        self.orders.splice(index..index, added.clone());
+        added
+    }
+
+    /// Queue the commands after the orders of the same or a higher priority.
+    pub fn append(&mut self, commands: Vec<Command>, priority: Priority) -> Vec<QueuedOrder> {
+        let index = self
+            .orders
+            .partition_point(|order| order.priority >= priority);
+        self.insert(index, commands, priority)
+    }
+
+    /// Queue the commands ahead of the orders of the same or a lower priority, they run next
+    /// unless a higher priority order is waiting.
+    pub fn prepend(&mut self, commands: Vec<Command>, priority: Priority) -> Vec<QueuedOrder> {
+        let index = self
+            .orders
+            .partition_point(|order| order.priority > priority);
+        self.insert(index, commands, priority)
+    }
+
+    /// Drop every order and queue the commands instead.
+    pub fn replace(&mut self, commands: Vec<Command>, priority: Priority) -> Vec<QueuedOrder> {
+        self.orders.clear();
+        self.insert(0, commands, priority)
+    }
+
+    /// Remove an order, `None` when it isn't queued.
+    pub fn cancel(&mut self, id: u32) -> Option<QueuedOrder> {
+        let index = self.orders.iter().position(|order| order.id == id)?;
+        Some(self.orders.remove(index))
+    }
+
+    /// Replace the command of a queued order, e.g. with the part of a move left to run.
+    pub fn update(&mut self, id: u32, command: Command) {
+        if let Some(order) = self.orders.iter_mut().find(|order| order.id == id) {
+            order.command = command;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OrderQueue, Priority};
+    use crate::turtle::{Command, CommandName};
+
+    fn commands(names: &[CommandName]) -> Vec<Command> {
+        names
+            .iter()
+            .map(|name| Command::new(name.clone(), 1))
+            .collect()
+    }
+
+    #[test]
+    fn test_priorities() {
+        let mut queue = OrderQueue::default();
+        queue.append(
+            commands(&[CommandName::Forward, CommandName::Up]),
+            Priority::Normal,
+        );
+        queue.append(commands(&[CommandName::Home]), Priority::Low);
+        queue.append(commands(&[CommandName::Locate]), Priority::High);
+        let down = queue.prepend(commands(&[CommandName::Down]), Priority::Normal);
+        queue.append(commands(&[CommandName::Left]), Priority::Normal);
+        let names: Vec<_> = queue
+            .commands()
+            .map(|command| command.name().clone())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                CommandName::Locate,
+                CommandName::Down,
+                CommandName::Forward,
+                CommandName::Up,
+                CommandName::Left,
+                CommandName::Home,
+            ]
+        );
+
+        assert_eq!(queue.cancel(down[0].id), Some(down[0].clone()));
+        assert_eq!(queue.cancel(down[0].id), None);
+        assert_eq!(queue.orders().len(), 5);
+        // Ids are never given twice
+        let replaced = queue.replace(commands(&[CommandName::Sleep]), Priority::Normal);
+        assert_eq!(replaced[0].id, 7);
+        assert_eq!(queue.orders(), replaced);
+    }
+}
@@ -0,0 +1,452 @@
+//! Shortest paths for the turtles, avoiding the blocks that must not be dug.
+//!
+//! Turtles dig through whatever is in their way, so only known obstacles (chests, other turtles,
+//! protected areas) are avoided. Long trips go up to a cruising altitude first.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::{
+    turtle::{Command, CommandName},
+    utils::{Area, Direction, Position},
+};
+
+/// Blocks around the start and the goal the search may go through.
+const SEARCH_MARGIN: i32 = 8;
+/// States visited before giving up.
+const MAX_VISITED: usize = 200_000;
+
+/// Blocks a turtle must not go through.
+pub trait Obstacles {
+    fn is_blocked(&self, position: &Position) -> bool;
+
+    /// Whether the block at `position` must never be dug, unlike another turtle passing by.
+    fn is_avoided(&self, position: &Position) -> bool;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KnownObstacles {
+    pub blocks: HashSet<Position>,
+    pub areas: Vec<Area>,
+    /// Avoided blocks of the world map, e.g. bedrock, also in `blocks`.
+    pub avoided: HashSet<Position>,
+}
+
+impl Obstacles for KnownObstacles {
+    fn is_blocked(&self, position: &Position) -> bool {
+        self.blocks.contains(position) || self.areas.iter().any(|area| area.contains(position))
+    }
+
+    fn is_avoided(&self, position: &Position) -> bool {
+        self.avoided.contains(position)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PathOptions {
+    /// Altitude of the trips covering at least `cruise_min_distance` blocks horizontally.
+    pub cruising_altitude: Option<i32>,
+    pub cruise_min_distance: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Action {
+    Forward,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Action {
+    const ALL: [Action; 5] = [
+        Action::Forward,
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+    ];
+
+    fn command_name(self) -> CommandName {
+        match self {
+            Action::Forward => CommandName::Forward,
+            Action::Up => CommandName::Up,
+            Action::Down => CommandName::Down,
+            Action::Left => CommandName::Left,
+            Action::Right => CommandName::Right,
+        }
+    }
+}
+
+/// Actions done, then changes of action, so that paths of the same length prefer fewer commands.
+type Cost = (u32, u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    position: Position,
+    direction: Direction,
+}
+
+impl State {
+    fn next(&self, action: Action) -> State {
+        let mut next = *self;
+        match action {
+            Action::Forward => next.position = self.position + self.direction.forward_vector(),
+            Action::Up => next.position.y += 1,
+            Action::Down => next.position.y -= 1,
+            Action::Left => next.direction = self.direction.turn_left(1),
+            Action::Right => next.direction = self.direction.turn_right(1),
+        }
+        next
+    }
+}
+
+fn distance(from: &Position, to: &Position) -> u32 {
+    let diff = *from - *to;
+    diff.x.unsigned_abs() + diff.y.unsigned_abs() + diff.z.unsigned_abs()
+}
+
+/// Quarter turns needed at least to face every horizontal direction towards `goal`, and `wanted`.
+fn turns_needed(state: &State, goal: &Position, wanted: Option<Direction>) -> u32 {
+    let diff = *goal - state.position;
+    let towards_x = match diff.x.signum() {
+        1 => Some(Direction::East),
+        -1 => Some(Direction::West),
+        _ => None,
+    };
+    let towards_z = match diff.z.signum() {
+        1 => Some(Direction::South),
+        -1 => Some(Direction::North),
+        _ => None,
+    };
+    [towards_x, towards_z, wanted]
+        .into_iter()
+        .flatten()
+        .map(|direction| state.direction.turns_to(direction).unsigned_abs())
+        .max()
+        .unwrap_or(0)
+}
+
+/// A* from `start` to `goal`, facing `wanted` at the end when given.
+fn search(
+    start: Position,
+    facing: Direction,
+    goal: Position,
+    wanted: Option<Direction>,
+    obstacles: &dyn Obstacles,
+) -> Option<(Vec<Action>, Direction)> {
+    if obstacles.is_blocked(&goal) {
+        return None;
+    }
+    let bounds = Area::new(start, goal).grow(SEARCH_MARGIN);
+    let start = State {
+        position: start,
+        direction: facing,
+    };
+    let estimate =
+        |state: &State| distance(&state.position, &goal) + turns_needed(state, &goal, wanted);
+    // The heap holds the indices of the states, with their estimated and current costs. Among the
+    // same estimates the states furthest from the start, so closest to the goal, come first, then
+    // the ones with the fewest changes, then the first ones found.
+    let mut states = vec![start];
+    let mut open = BinaryHeap::from([(Reverse(estimate(&start)), 0, Reverse(0), Reverse(0))]);
+    let mut costs: HashMap<State, Cost> = HashMap::from([(start, (0, 0))]);
+    // The action reaching a state on its best path, to count the changes
+    let mut came_from: HashMap<State, (State, Action)> = HashMap::new();
+    while let Some((_, steps, Reverse(changes), Reverse(index))) = open.pop() {
+        let state = states[index];
+        if costs
+            .get(&state)
+            .is_some_and(|best| *best < (steps, changes))
+        {
+            continue;
+        }
+        if state.position == goal && wanted.is_none_or(|wanted| wanted == state.direction) {
+            let mut actions = Vec::new();
+            let mut current = state;
+            while let Some((previous, action)) = came_from.get(&current) {
+                actions.push(*action);
+                current = *previous;
+            }
+            actions.reverse();
+            return Some((actions, state.direction));
+        }
+        if costs.len() > MAX_VISITED {
+            return None;
+        }
+        for action in Action::ALL {
+            let next = state.next(action);
+            if !bounds.contains(&next.position) || obstacles.is_blocked(&next.position) {
+                continue;
+            }
+            let last = came_from.get(&state).map(|(_, last)| *last);
+            let next_cost = (
+                steps + 1,
+                changes + u32::from(last.is_some_and(|last| last != action)),
+            );
+            if costs.get(&next).is_none_or(|best| next_cost < *best) {
+                costs.insert(next, next_cost);
+                came_from.insert(next, (state, action));
+                open.push((
+                    Reverse(next_cost.0 + estimate(&next)),
+                    next_cost.0,
+                    Reverse(next_cost.1),
+                    Reverse(states.len()),
+                ));
+                states.push(next);
+            }
+        }
+    }
+    None
+}
+
+/// Merge the repeated actions into commands, e.g. three `Forward` into `Forward(3)`.
+fn compress(actions: &[Action]) -> Vec<Command> {
+    let mut commands: Vec<(Action, i32)> = Vec::new();
+    for action in actions {
+        match commands.last_mut() {
+            Some((last, count)) if last == action => *count += 1,
+            _ => commands.push((*action, 1)),
+        }
+    }
+    commands
+        .into_iter()
+        .map(|(action, count)| Command::new(action.command_name(), count))
+        .collect()
+}
+
+/// Commands to go from `start` to `goal` without going through obstacles, facing `wanted` at the
+/// end when given. `None` when there is no path near the two positions.
+pub fn find_path(
+    start: Position,
+    facing: Direction,
+    goal: Position,
+    wanted: Option<Direction>,
+    obstacles: &dyn Obstacles,
+    options: &PathOptions,
+) -> Option<Vec<Command>> {
+    let horizontal_distance = (start.x - goal.x).abs() + (start.z - goal.z).abs();
+    let mut waypoints = Vec::new();
+    if let Some(altitude) = options.cruising_altitude {
+        if horizontal_distance >= options.cruise_min_distance {
+            waypoints.push(Position {
+                y: altitude,
+                ..start
+            });
+            waypoints.push(Position {
+                y: altitude,
+                ..goal
+            });
+        }
+    }
+    waypoints.push(goal);
+
+    let mut actions = Vec::new();
+    let mut position = start;
+    let mut direction = facing;
+    let last = waypoints.len() - 1;
+    for (index, waypoint) in waypoints.into_iter().enumerate() {
+        let wanted = wanted.filter(|_| index == last);
+        let (mut leg, facing) = search(position, direction, waypoint, wanted, obstacles)?;
+        actions.append(&mut leg);
+        position = waypoint;
+        direction = facing;
+    }
+    Some(compress(&actions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_path, KnownObstacles, Obstacles, PathOptions};
+    use crate::{
+        turtle::{Command, CommandName},
+        utils::{Area, Direction, Position},
+    };
+
+    /// Run the commands, checking that no obstacle is crossed.
+    fn follow(
+        commands: &[Command],
+        mut position: Position,
+        mut direction: Direction,
+        obstacles: &dyn Obstacles,
+    ) -> (Position, Direction) {
+        for command in commands {
+            for _ in 0..command.argument() {
+                match command.name() {
+                    CommandName::Forward => position = position + direction.forward_vector(),
+                    CommandName::Up => position.y += 1,
+                    CommandName::Down => position.y -= 1,
+                    CommandName::Left => direction = direction.turn_left(1),
+                    CommandName::Right => direction = direction.turn_right(1),
+                    name => panic!("Unexpected command {}", name),
+                }
+                assert!(
+                    !obstacles.is_blocked(&position),
+                    "Went through {:?}",
+                    position
+                );
+            }
+        }
+        (position, direction)
+    }
+
+    #[test]
+    fn test_straight_path() {
+        let start = Position { x: 0, y: 0, z: 0 };
+        let goal = Position { x: 0, y: 2, z: -5 };
+        let obstacles = KnownObstacles::default();
+        let path = find_path(
+            start,
+            Direction::North,
+            goal,
+            Some(Direction::West),
+            &obstacles,
+            &PathOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(path.len(), 3);
+        assert!(path.contains(&Command::new(CommandName::Forward, 5)));
+        assert!(path.contains(&Command::new(CommandName::Up, 2)));
+        assert!(path.contains(&Command::new(CommandName::Left, 1)));
+        assert_eq!(
+            follow(&path, start, Direction::North, &obstacles),
+            (goal, Direction::West)
+        );
+    }
+
+    #[test]
+    fn test_avoid_obstacles() {
+        let start = Position { x: 0, y: 0, z: 0 };
+        let goal = Position { x: 6, y: 0, z: 0 };
+        let mut obstacles = KnownObstacles::default();
+        // A wall between start and goal, with a chest on top
+        obstacles.areas.push(Area::new(
+            Position {
+                x: 3,
+                y: -20,
+                z: -20,
+            },
+            Position { x: 3, y: 0, z: 20 },
+        ));
+        obstacles.blocks.insert(Position { x: 3, y: 1, z: 0 });
+        let path = find_path(
+            start,
+            Direction::East,
+            goal,
+            Some(Direction::East),
+            &obstacles,
+            &PathOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            follow(&path, start, Direction::East, &obstacles),
+            (goal, Direction::East)
+        );
+        let moves: i32 = path
+            .iter()
+            .filter(|command| {
+                matches!(
+                    command.name(),
+                    CommandName::Forward | CommandName::Up | CommandName::Down
+                )
+            })
+            .map(Command::argument)
+            .sum();
+        // Around the chest, one block higher
+        assert_eq!(moves, 6 + 2 * 2);
+
+        obstacles.blocks.insert(goal);
+        assert!(find_path(
+            start,
+            Direction::East,
+            goal,
+            Some(Direction::East),
+            &obstacles,
+            &PathOptions::default()
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_cruising_altitude() {
+        let start = Position { x: 0, y: 10, z: 0 };
+        let goal = Position { x: 40, y: 12, z: 0 };
+        let obstacles = KnownObstacles::default();
+        let options = PathOptions {
+            cruising_altitude: Some(30),
+            cruise_min_distance: 32,
+        };
+        let path = find_path(
+            start,
+            Direction::East,
+            goal,
+            Some(Direction::East),
+            &obstacles,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            path,
+            vec![
+                Command::new(CommandName::Up, 20),
+                Command::new(CommandName::Forward, 40),
+                Command::new(CommandName::Down, 18),
+            ]
+        );
+
+        let near = Position { x: 10, y: 12, z: 0 };
+        let path = find_path(
+            start,
+            Direction::East,
+            near,
+            Some(Direction::East),
+            &obstacles,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(follow(&path, start, Direction::East, &obstacles).0, near);
+        assert_eq!(path.len(), 2);
+    }
+
+    #[test]
+    fn test_long_path() {
+        // From the front of the chest, across the whole map
+        let chest = Position { x: 0, y: 64, z: 0 };
+        let start = chest + Direction::North.forward_vector();
+        let mut obstacles = KnownObstacles::default();
+        obstacles.blocks.insert(chest);
+        for goal in [
+            Position {
+                x: 30,
+                y: -15,
+                z: -36,
+            },
+            Position {
+                x: 30,
+                y: -95,
+                z: -36,
+            },
+            Position {
+                x: -60,
+                y: 40,
+                z: 60,
+            },
+        ] {
+            let path = find_path(
+                start,
+                Direction::North,
+                goal,
+                Some(Direction::South),
+                &obstacles,
+                &PathOptions::default(),
+            )
+            .unwrap_or_else(|| panic!("No path to {:?}", goal));
+            assert_eq!(
+                follow(&path, start, Direction::North, &obstacles),
+                (goal, Direction::South)
+            );
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use async_trait::async_trait;
+use std::{collections::HashMap, sync::Mutex};
+
+use super::{Storage, StorageResult};
+use crate::inventory::Inventory;
+use crate::mining_areas::MiningArea;
+use crate::mining_plots::MiningPlot;
+use crate::order_queue::OrderQueue;
+use crate::stations::Station;
+use crate::telemetry::TelemetrySample;
+use crate::turtle::Turtle;
+use crate::utils::{Area, Position};
+use crate::world_map::Block;
+
+/// Storage kept in memory, lost when the server stops. Useful to run locally and in tests.
+#[derive(Default)]
+pub struct MemoryStorage {
+    turtles: Mutex<HashMap<String, Turtle>>,
+    mining_plots: Mutex<Vec<MiningPlot>>,
+    mining_areas: Mutex<HashMap<String, MiningArea>>,
+    stations: Mutex<HashMap<String, Station>>,
+    telemetry: Mutex<Vec<TelemetrySample>>,
+    blocks: Mutex<HashMap<Position, Block>>,
+}
+
+impl MemoryStorage {
+    fn update_turtle(&self, name: &str, update: impl FnOnce(&mut Turtle)) -> bool {
+        match self.turtles.lock().unwrap().get_mut(name) {
+            Some(turtle) => {
+                update(turtle);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn update_turtle_plot(&self, turtle_name: &str, update: impl FnOnce(&mut MiningPlot)) {
+        let mut mining_plots = self.mining_plots.lock().unwrap();
+        if let Some(plot) = mining_plots
+            .iter_mut()
+            .find(|plot| plot.current_turtle.as_deref() == Some(turtle_name))
+        {
+            update(plot);
+        }
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn find_turtle(&self, name: &str) -> StorageResult<Option<Turtle>> {
+        Ok(self.turtles.lock().unwrap().get(name).cloned())
+    }
+
+    async fn list_turtles(&self) -> StorageResult<Vec<Turtle>> {
+        Ok(self.turtles.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn insert_turtle(&self, turtle: &Turtle) -> StorageResult<()> {
+        self.turtles
+            .lock()
+            .unwrap()
+            .insert(turtle.name().to_string(), turtle.clone());
+        Ok(())
+    }
+
+    async fn save_turtle(&self, turtle: &Turtle) -> StorageResult<bool> {
+        Ok(self.update_turtle(turtle.name(), |saved| *saved = turtle.clone()))
+    }
+
+    async fn set_turtle_queue(&self, name: &str, queue: &OrderQueue) -> StorageResult<bool> {
+        Ok(self.update_turtle(name, |turtle| turtle.queue = queue.clone()))
+    }
+
+    async fn set_turtle_offline(&self, name: &str, offline: bool) -> StorageResult<bool> {
+        Ok(self.update_turtle(name, |turtle| turtle.offline = offline))
+    }
+
+    async fn set_turtle_area(&self, name: &str, area: &str) -> StorageResult<bool> {
+        Ok(self.update_turtle(name, |turtle| turtle.area = area.to_string()))
+    }
+
+    async fn set_turtle_inventory(&self, name: &str, inventory: &Inventory) -> StorageResult<bool> {
+        Ok(self.update_turtle(name, |turtle| turtle.inventory = Some(inventory.clone())))
+    }
+
+    async fn find_turtle_plot(&self, turtle_name: &str) -> StorageResult<Option<MiningPlot>> {
+        Ok(self
+            .mining_plots
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|plot| plot.current_turtle.as_deref() == Some(turtle_name))
+            .cloned())
+    }
+
+    async fn find_plot(&self, id: u32) -> StorageResult<Option<MiningPlot>> {
+        Ok(self
+            .mining_plots
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|plot| plot.id == id)
+            .cloned())
+    }
+
+    async fn list_plots(&self) -> StorageResult<Vec<MiningPlot>> {
+        Ok(self.mining_plots.lock().unwrap().clone())
+    }
+
+    async fn create_plot(
+        &self,
+        area: &str,
+        build: &(dyn Fn(u32, u32) -> Option<MiningPlot> + Sync),
+    ) -> StorageResult<Option<MiningPlot>> {
+        let mut mining_plots = self.mining_plots.lock().unwrap();
+        let index = mining_plots.iter().filter(|plot| plot.area == area).count();
+        let plot = build(mining_plots.len() as u32, index as u32);
+        mining_plots.extend(plot.clone());
+        Ok(plot)
+    }
+
+    async fn save_plot(&self, plot: &MiningPlot) -> StorageResult<bool> {
+        let mut mining_plots = self.mining_plots.lock().unwrap();
+        match mining_plots.iter_mut().find(|saved| saved.id == plot.id) {
+            Some(saved) => {
+                *saved = plot.clone();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn set_plot_depth_segment(&self, turtle_name: &str, segment: u32) -> StorageResult<()> {
+        self.update_turtle_plot(turtle_name, |plot| plot.mined_depth_segment = segment);
+        Ok(())
+    }
+
+    async fn release_turtle_plot(&self, turtle_name: &str) -> StorageResult<()> {
+        self.update_turtle_plot(turtle_name, |plot| plot.current_turtle = None);
+        Ok(())
+    }
+
+    async fn claim_free_plot(
+        &self,
+        turtle_name: &str,
+        area: &str,
+    ) -> StorageResult<Option<MiningPlot>> {
+        let mut mining_plots = self.mining_plots.lock().unwrap();
+        Ok(mining_plots
+            .iter_mut()
+            .filter(|plot| plot.area == area && plot.current_turtle.is_none() && !plot.finished)
+            .min_by_key(|plot| plot.id)
+            .map(|plot| {
+                plot.current_turtle = Some(turtle_name.to_string());
+                plot.clone()
+            }))
+    }
+
+    async fn list_areas(&self) -> StorageResult<Vec<MiningArea>> {
+        let mut areas: Vec<_> = self
+            .mining_areas
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect();
+        areas.sort_by(|first, second| first.name.cmp(&second.name));
+        Ok(areas)
+    }
+
+    async fn find_area(&self, name: &str) -> StorageResult<Option<MiningArea>> {
+        Ok(self.mining_areas.lock().unwrap().get(name).cloned())
+    }
+
+    async fn save_area(&self, area: &MiningArea) -> StorageResult<()> {
+        self.mining_areas
+            .lock()
+            .unwrap()
+            .insert(area.name.clone(), area.clone());
+        Ok(())
+    }
+
+    async fn list_stations(&self) -> StorageResult<Vec<Station>> {
+        let mut stations: Vec<_> = self.stations.lock().unwrap().values().cloned().collect();
+        stations.sort_by(|first, second| first.name.cmp(&second.name));
+        Ok(stations)
+    }
+
+    async fn save_station(&self, station: &Station) -> StorageResult<()> {
+        self.stations
+            .lock()
+            .unwrap()
+            .insert(station.name.clone(), station.clone());
+        Ok(())
+    }
+
+    async fn delete_station(&self, name: &str) -> StorageResult<bool> {
+        Ok(self.stations.lock().unwrap().remove(name).is_some())
+    }
+
+    async fn set_station_capacity(&self, name: &str, capacity: u32) -> StorageResult<bool> {
+        match self.stations.lock().unwrap().get_mut(name) {
+            Some(station) => {
+                station.capacity = Some(capacity);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn add_telemetry_sample(&self, sample: &TelemetrySample) -> StorageResult<()> {
+        self.telemetry.lock().unwrap().push(sample.clone());
+        Ok(())
+    }
+
+    async fn find_telemetry_samples(
+        &self,
+        turtle: &str,
+        topic: &str,
+        from: u64,
+        to: u64,
+    ) -> StorageResult<Vec<TelemetrySample>> {
+        let mut samples: Vec<_> = self
+            .telemetry
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|sample| {
+                sample.turtle == turtle
+                    && sample.topic == topic
+                    && (from..=to).contains(&sample.time)
+            })
+            .cloned()
+            .collect();
+        samples.sort_by_key(|sample| sample.time);
+        Ok(samples)
+    }
+
+    async fn delete_telemetry_samples(&self, turtle: &str, time: u64) -> StorageResult<()> {
+        self.telemetry
+            .lock()
+            .unwrap()
+            .retain(|sample| sample.turtle != turtle || sample.time >= time);
+        Ok(())
+    }
+
+    async fn save_blocks(&self, blocks: &[Block]) -> StorageResult<()> {
+        let mut saved = self.blocks.lock().unwrap();
+        for block in blocks {
+            saved.insert(block.position, block.clone());
+        }
+        Ok(())
+    }
+
+    async fn find_blocks(&self, area: &Area) -> StorageResult<Vec<Block>> {
+        Ok(self
+            .blocks
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|block| area.contains(&block.position))
+            .cloned()
+            .collect())
+    }
+}
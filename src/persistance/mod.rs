@@ -0,0 +1,154 @@
+use actix_web::ResponseError;
+use async_trait::async_trait;
+use std::{fmt, sync::Arc};
+
+use crate::config::{DatabaseBackend, DatabaseConfig};
+use crate::inventory::Inventory;
+use crate::mining_areas::MiningArea;
+use crate::mining_plots::MiningPlot;
+use crate::order_queue::OrderQueue;
+use crate::stations::Station;
+use crate::telemetry::TelemetrySample;
+use crate::turtle::Turtle;
+use crate::utils::Area;
+use crate::world_map::Block;
+
+mod memory;
+mod mongo;
+
+pub use memory::MemoryStorage;
+pub use mongo::MongoStorage;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Mongo(mongodb::error::Error),
+    Serialization(mongodb::bson::ser::Error),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Mongo(error) => write!(f, "MongoDB error: {}", error),
+            StorageError::Serialization(error) => write!(f, "Serialization error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl ResponseError for StorageError {}
+
+impl From<mongodb::error::Error> for StorageError {
+    fn from(error: mongodb::error::Error) -> Self {
+        StorageError::Mongo(error)
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for StorageError {
+    fn from(error: mongodb::bson::ser::Error) -> Self {
+        StorageError::Serialization(error)
+    }
+}
+
+pub type StorageResult<T> = Result<T, StorageError>;
+
+/// Where turtles and mining plots are kept.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn find_turtle(&self, name: &str) -> StorageResult<Option<Turtle>>;
+
+    async fn list_turtles(&self) -> StorageResult<Vec<Turtle>>;
+
+    async fn insert_turtle(&self, turtle: &Turtle) -> StorageResult<()>;
+
+    /// Replace the turtle with the same name, returns `false` if there is none.
+    async fn save_turtle(&self, turtle: &Turtle) -> StorageResult<bool>;
+
+    async fn set_turtle_queue(&self, name: &str, queue: &OrderQueue) -> StorageResult<bool>;
+
+    async fn set_turtle_offline(&self, name: &str, offline: bool) -> StorageResult<bool>;
+
+    async fn set_turtle_area(&self, name: &str, area: &str) -> StorageResult<bool>;
+
+    async fn set_turtle_inventory(&self, name: &str, inventory: &Inventory) -> StorageResult<bool>;
+
+    /// The plot currently being mined by a turtle.
+    async fn find_turtle_plot(&self, turtle_name: &str) -> StorageResult<Option<MiningPlot>>;
+
+    async fn find_plot(&self, id: u32) -> StorageResult<Option<MiningPlot>>;
+
+    async fn list_plots(&self) -> StorageResult<Vec<MiningPlot>>;
+
+    /// Insert the plot built by `build` from the next free id and the next free index of the
+    /// area, if it builds one. Concurrent calls never get the same id nor index.
+    async fn create_plot(
+        &self,
+        area: &str,
+        build: &(dyn Fn(u32, u32) -> Option<MiningPlot> + Sync),
+    ) -> StorageResult<Option<MiningPlot>>;
+
+    /// Replace the plot with the same id, returns false when there is none.
+    async fn save_plot(&self, plot: &MiningPlot) -> StorageResult<bool>;
+
+    async fn set_plot_depth_segment(&self, turtle_name: &str, segment: u32) -> StorageResult<()>;
+
+    /// Unassign the plot currently being mined by a turtle.
+    async fn release_turtle_plot(&self, turtle_name: &str) -> StorageResult<()>;
+
+    /// Assign the unfinished plot of the area without a turtle with the lowest id to the turtle.
+    /// Concurrent calls never get the same plot.
+    async fn claim_free_plot(
+        &self,
+        turtle_name: &str,
+        area: &str,
+    ) -> StorageResult<Option<MiningPlot>>;
+
+    /// The mining areas created through the API, without the default one.
+    async fn list_areas(&self) -> StorageResult<Vec<MiningArea>>;
+
+    async fn find_area(&self, name: &str) -> StorageResult<Option<MiningArea>>;
+
+    /// Insert the area, or replace the one with the same name.
+    async fn save_area(&self, area: &MiningArea) -> StorageResult<()>;
+
+    async fn list_stations(&self) -> StorageResult<Vec<Station>>;
+
+    /// Insert the station, or replace the one with the same name.
+    async fn save_station(&self, station: &Station) -> StorageResult<()>;
+
+    /// Returns `false` when there is no station with this name.
+    async fn delete_station(&self, name: &str) -> StorageResult<bool>;
+
+    async fn set_station_capacity(&self, name: &str, capacity: u32) -> StorageResult<bool>;
+
+    async fn add_telemetry_sample(&self, sample: &TelemetrySample) -> StorageResult<()>;
+
+    /// The samples of a topic posted by the turtle from `from` to `to` included, sorted by time.
+    async fn find_telemetry_samples(
+        &self,
+        turtle: &str,
+        topic: &str,
+        from: u64,
+        to: u64,
+    ) -> StorageResult<Vec<TelemetrySample>>;
+
+    /// Drop the samples of the turtle posted before `time`.
+    async fn delete_telemetry_samples(&self, turtle: &str, time: u64) -> StorageResult<()>;
+
+    /// Add blocks to the world map, replacing the ones at the same positions.
+    async fn save_blocks(&self, blocks: &[Block]) -> StorageResult<()>;
+
+    async fn find_blocks(&self, area: &Area) -> StorageResult<Vec<Block>>;
+}
+
+pub async fn connect(config: &DatabaseConfig) -> Arc<dyn Storage> {
+    match config.backend {
+        DatabaseBackend::Memory => {
+            log::warn!("Using in-memory storage, nothing will be persisted");
+            Arc::new(MemoryStorage::default())
+        }
+        DatabaseBackend::Mongodb => {
+            Arc::new(MongoStorage::connect(&config.uri, &config.name).await)
+        }
+    }
+}
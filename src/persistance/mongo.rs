@@ -0,0 +1,604 @@
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{
+    ClientOptions, FindOneAndUpdateOptions, FindOptions, IndexOptions, ReplaceOptions,
+    ReturnDocument, UpdateOptions,
+};
+use mongodb::{Client, Collection, IndexModel};
+use serde::Deserialize;
+
+use super::{Storage, StorageResult};
+use crate::inventory::Inventory;
+use crate::mining_areas::{MiningArea, DEFAULT_AREA};
+use crate::mining_plots::MiningPlot;
+use crate::order_queue::OrderQueue;
+use crate::stations::Station;
+use crate::telemetry::TelemetrySample;
+use crate::turtle::Turtle;
+use crate::utils::{Area, Position};
+use crate::world_map::Block;
+
+/// Id of the counter of the mining plots ids in the `counters` collection, followed by `:area`
+/// for the counter of the indexes in an area.
+const PLOTS_COUNTER: &str = "miningplot";
+/// Code of the write errors caused by a unique index.
+const DUPLICATE_KEY: i32 = 11000;
+
+/// Number of ids given so far.
+#[derive(Deserialize)]
+struct Counter {
+    value: u32,
+}
+
+pub struct MongoStorage {
+    turtles: Collection<Turtle>,
+    mining_plots: Collection<MiningPlot>,
+    mining_areas: Collection<MiningArea>,
+    stations: Collection<Station>,
+    telemetry: Collection<TelemetrySample>,
+    blocks: Collection<Block>,
+    counters: Collection<Counter>,
+}
+
+fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    matches!(
+        &*error.kind,
+        ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == DUPLICATE_KEY
+    )
+}
+
+async fn create_index<T>(collection: &Collection<T>, keys: bson::Document, unique: bool) {
+    let index = IndexModel::builder()
+        .keys(keys)
+        .options(IndexOptions::builder().unique(unique).build())
+        .build();
+    if let Err(error) = collection.create_index(index, None).await {
+        log::error!(
+            "Unable to create the index of the {}: {}",
+            collection.name(),
+            error
+        );
+    }
+}
+
+fn area_counter(area: &str) -> String {
+    format!("{}:{}", PLOTS_COUNTER, area)
+}
+
+/// Fields given to the plots created before the ids or the mining areas, and the first values
+/// of the counters.
+#[derive(Debug, PartialEq)]
+struct PlotMigration {
+    /// `$set` of each plot to update, by `_id`.
+    updates: Vec<(Bson, Document)>,
+    next_id: u32,
+    /// Next index in the default area.
+    next_index: u32,
+}
+
+fn get_u32(plot: &Document, key: &str) -> Option<u32> {
+    match plot.get(key)? {
+        Bson::Int32(value) => u32::try_from(*value).ok(),
+        Bson::Int64(value) => u32::try_from(*value).ok(),
+        _ => None,
+    }
+}
+
+/// Number the plots without an id after the others, in the order of `plots` (their creation
+/// order), and move the plots without an area to the default one, their index being their id.
+fn plan_plot_migration(plots: &[Document]) -> PlotMigration {
+    let mut next_id = plots
+        .iter()
+        .filter_map(|plot| get_u32(plot, "id"))
+        .max()
+        .map_or(0, |id| id + 1);
+    let mut next_index = 0;
+    let mut updates = Vec::new();
+    for plot in plots {
+        let mut set = Document::new();
+        let id = get_u32(plot, "id").unwrap_or_else(|| {
+            set.insert("id", next_id);
+            next_id += 1;
+            next_id - 1
+        });
+        let area = plot.get_str("area").ok();
+        let index = if area.is_none() {
+            set.insert("area", DEFAULT_AREA);
+            set.insert("index", id);
+            Some(id)
+        } else {
+            get_u32(plot, "index").filter(|_| area == Some(DEFAULT_AREA))
+        };
+        if let Some(index) = index {
+            next_index = next_index.max(index + 1);
+        }
+        if let Some(object_id) = plot.get("_id").filter(|_| !set.is_empty()) {
+            updates.push((object_id.clone(), set));
+        }
+    }
+    PlotMigration {
+        updates,
+        next_id,
+        next_index,
+    }
+}
+
+/// Give an id and an area to the plots created before them, and start the counters after the
+/// existing plots. Must run before the unique indexes are built.
+async fn migrate_plots(mining_plots: &Collection<MiningPlot>, counters: &Collection<Counter>) {
+    let migration = async {
+        let plots: Vec<Document> = mining_plots
+            .clone_with_type::<Document>()
+            .find(None, FindOptions::builder().sort(doc! { "_id": 1 }).build())
+            .await?
+            .try_collect()
+            .await?;
+        let migration = plan_plot_migration(&plots);
+        for (id, set) in &migration.updates {
+            mining_plots
+                .update_one(doc! { "_id": id }, doc! { "$set": set }, None)
+                .await?;
+        }
+        if !migration.updates.is_empty() {
+            log::info!(
+                "Numbered {} mining plots created before the ids or the areas",
+                migration.updates.len()
+            );
+        }
+        for (counter, value) in [
+            (PLOTS_COUNTER.to_string(), migration.next_id),
+            (area_counter(DEFAULT_AREA), migration.next_index),
+        ] {
+            counters
+                .update_one(
+                    doc! { "_id": counter },
+                    doc! { "$max": { "value": value } },
+                    UpdateOptions::builder().upsert(true).build(),
+                )
+                .await?;
+        }
+        Ok::<_, mongodb::error::Error>(())
+    };
+    if let Err(error) = migration.await {
+        log::error!("Unable to migrate the mining plots: {}", error);
+    }
+}
+
+fn position_filter(position: &Position) -> bson::Document {
+    doc! { "position.x": position.x, "position.y": position.y, "position.z": position.z }
+}
+
+impl MongoStorage {
+    pub async fn connect(uri: &str, database: &str) -> Self {
+        let client_options = ClientOptions::parse(uri)
+            .await
+            .expect("Unable to connect to the database");
+        let client = Client::with_options(client_options).unwrap();
+        let db = client.database(database);
+
+        let blocks = db.collection::<Block>("blocks");
+        create_index(
+            &blocks,
+            doc! { "position.x": 1, "position.y": 1, "position.z": 1 },
+            true,
+        )
+        .await;
+        // Two plots with the same id or index would be mined at the same position
+        let mining_plots = db.collection::<MiningPlot>("miningplot");
+        let counters = db.collection::<Counter>("counters");
+        migrate_plots(&mining_plots, &counters).await;
+        create_index(&mining_plots, doc! { "id": 1 }, true).await;
+        create_index(&mining_plots, doc! { "area": 1, "index": 1 }, true).await;
+        let mining_areas = db.collection::<MiningArea>("miningarea");
+        create_index(&mining_areas, doc! { "name": 1 }, true).await;
+        let stations = db.collection::<Station>("stations");
+        create_index(&stations, doc! { "name": 1 }, true).await;
+        let telemetry = db.collection::<TelemetrySample>("telemetry");
+        create_index(
+            &telemetry,
+            doc! { "turtle": 1, "topic": 1, "time": 1 },
+            false,
+        )
+        .await;
+
+        MongoStorage {
+            turtles: db.collection::<Turtle>("turtles"),
+            mining_plots,
+            mining_areas,
+            stations,
+            telemetry,
+            blocks,
+            counters,
+        }
+    }
+
+    /// Increment a counter of the `counters` collection, returns the value before.
+    async fn next_value(&self, counter: &str) -> StorageResult<u32> {
+        let options = FindOneAndUpdateOptions::builder()
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .build();
+        let counter = self
+            .counters
+            .find_one_and_update(
+                doc! { "_id": counter },
+                doc! { "$inc": { "value": 1 } },
+                options,
+            )
+            .await?;
+        Ok(counter.map_or(0, |counter| counter.value - 1))
+    }
+}
+
+#[async_trait]
+impl Storage for MongoStorage {
+    async fn find_turtle(&self, name: &str) -> StorageResult<Option<Turtle>> {
+        Ok(self.turtles.find_one(doc! { "name": name }, None).await?)
+    }
+
+    async fn list_turtles(&self) -> StorageResult<Vec<Turtle>> {
+        Ok(self.turtles.find(None, None).await?.try_collect().await?)
+    }
+
+    async fn insert_turtle(&self, turtle: &Turtle) -> StorageResult<()> {
+        self.turtles.insert_one(turtle, None).await?;
+        Ok(())
+    }
+
+    async fn save_turtle(&self, turtle: &Turtle) -> StorageResult<bool> {
+        let result = self
+            .turtles
+            .replace_one(doc! { "name": turtle.name() }, turtle, None)
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn set_turtle_queue(&self, name: &str, queue: &OrderQueue) -> StorageResult<bool> {
+        let result = self
+            .turtles
+            .update_one(
+                doc! { "name": name },
+                doc! { "$set": { "queue": bson::to_bson(queue)? } },
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn set_turtle_offline(&self, name: &str, offline: bool) -> StorageResult<bool> {
+        let result = self
+            .turtles
+            .update_one(
+                doc! { "name": name },
+                doc! { "$set": { "offline": offline } },
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn set_turtle_area(&self, name: &str, area: &str) -> StorageResult<bool> {
+        let result = self
+            .turtles
+            .update_one(
+                doc! { "name": name },
+                doc! { "$set": { "area": area } },
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn set_turtle_inventory(&self, name: &str, inventory: &Inventory) -> StorageResult<bool> {
+        let result = self
+            .turtles
+            .update_one(
+                doc! { "name": name },
+                doc! { "$set": { "inventory": bson::to_bson(inventory)? } },
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn find_turtle_plot(&self, turtle_name: &str) -> StorageResult<Option<MiningPlot>> {
+        Ok(self
+            .mining_plots
+            .find_one(doc! { "current_turtle": turtle_name }, None)
+            .await?)
+    }
+
+    async fn find_plot(&self, id: u32) -> StorageResult<Option<MiningPlot>> {
+        Ok(self.mining_plots.find_one(doc! { "id": id }, None).await?)
+    }
+
+    async fn list_plots(&self) -> StorageResult<Vec<MiningPlot>> {
+        Ok(self
+            .mining_plots
+            .find(None, None)
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn create_plot(
+        &self,
+        area: &str,
+        build: &(dyn Fn(u32, u32) -> Option<MiningPlot> + Sync),
+    ) -> StorageResult<Option<MiningPlot>> {
+        loop {
+            let id = self.next_value(PLOTS_COUNTER).await?;
+            let index = self.next_value(&area_counter(area)).await?;
+            let plot = match build(id, index) {
+                Some(plot) => plot,
+                None => return Ok(None),
+            };
+            match self.mining_plots.insert_one(&plot, None).await {
+                Ok(_) => return Ok(Some(plot)),
+                // Plots created before the counters, skip their id and index
+                Err(error) if is_duplicate_key(&error) => log::warn!(
+                    "Mining plot {} or plot {} of {} already exists, trying the next ones",
+                    id,
+                    index,
+                    area
+                ),
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    async fn save_plot(&self, plot: &MiningPlot) -> StorageResult<bool> {
+        let result = self
+            .mining_plots
+            .replace_one(doc! { "id": plot.id }, plot, None)
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn set_plot_depth_segment(&self, turtle_name: &str, segment: u32) -> StorageResult<()> {
+        self.mining_plots
+            .update_one(
+                doc! { "current_turtle": turtle_name },
+                doc! { "$set": { "mined_depth_segment": segment } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn release_turtle_plot(&self, turtle_name: &str) -> StorageResult<()> {
+        self.mining_plots
+            .update_one(
+                doc! { "current_turtle": turtle_name },
+                doc! { "$set": { "current_turtle": bson::to_bson(&None::<String>)? } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn claim_free_plot(
+        &self,
+        turtle_name: &str,
+        area: &str,
+    ) -> StorageResult<Option<MiningPlot>> {
+        let options = FindOneAndUpdateOptions::builder()
+            .sort(doc! { "id": 1 })
+            .return_document(ReturnDocument::After)
+            .build();
+        Ok(self
+            .mining_plots
+            .find_one_and_update(
+                doc! { "area": area, "current_turtle": null, "finished": { "$ne": true } },
+                doc! { "$set": { "current_turtle": turtle_name } },
+                options,
+            )
+            .await?)
+    }
+
+    async fn list_areas(&self) -> StorageResult<Vec<MiningArea>> {
+        let options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        Ok(self
+            .mining_areas
+            .find(None, options)
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn find_area(&self, name: &str) -> StorageResult<Option<MiningArea>> {
+        Ok(self
+            .mining_areas
+            .find_one(doc! { "name": name }, None)
+            .await?)
+    }
+
+    async fn save_area(&self, area: &MiningArea) -> StorageResult<()> {
+        self.mining_areas
+            .replace_one(
+                doc! { "name": &area.name },
+                area,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn list_stations(&self) -> StorageResult<Vec<Station>> {
+        let options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+        Ok(self
+            .stations
+            .find(None, options)
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn save_station(&self, station: &Station) -> StorageResult<()> {
+        self.stations
+            .replace_one(
+                doc! { "name": &station.name },
+                station,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_station(&self, name: &str) -> StorageResult<bool> {
+        let result = self
+            .stations
+            .delete_one(doc! { "name": name }, None)
+            .await?;
+        Ok(result.deleted_count == 1)
+    }
+
+    async fn set_station_capacity(&self, name: &str, capacity: u32) -> StorageResult<bool> {
+        let result = self
+            .stations
+            .update_one(
+                doc! { "name": name },
+                doc! { "$set": { "capacity": capacity } },
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn add_telemetry_sample(&self, sample: &TelemetrySample) -> StorageResult<()> {
+        self.telemetry.insert_one(sample, None).await?;
+        Ok(())
+    }
+
+    async fn find_telemetry_samples(
+        &self,
+        turtle: &str,
+        topic: &str,
+        from: u64,
+        to: u64,
+    ) -> StorageResult<Vec<TelemetrySample>> {
+        let filter = doc! {
+            "turtle": turtle,
+            "topic": topic,
+            "time": { "$gte": from as i64, "$lte": to as i64 },
+        };
+        let options = FindOptions::builder().sort(doc! { "time": 1 }).build();
+        Ok(self
+            .telemetry
+            .find(filter, options)
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn delete_telemetry_samples(&self, turtle: &str, time: u64) -> StorageResult<()> {
+        self.telemetry
+            .delete_many(
+                doc! { "turtle": turtle, "time": { "$lt": time as i64 } },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn save_blocks(&self, blocks: &[Block]) -> StorageResult<()> {
+        let options = ReplaceOptions::builder().upsert(true).build();
+        for block in blocks {
+            self.blocks
+                .replace_one(position_filter(&block.position), block, options.clone())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn find_blocks(&self, area: &Area) -> StorageResult<Vec<Block>> {
+        let (min, max) = (area.min(), area.max());
+        let filter = doc! {
+            "position.x": { "$gte": min.x, "$lte": max.x },
+            "position.y": { "$gte": min.y, "$lte": max.y },
+            "position.z": { "$gte": min.z, "$lte": max.z },
+        };
+        Ok(self.blocks.find(filter, None).await?.try_collect().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan_plot_migration, PlotMigration};
+    use crate::mining_plots::MiningPlot;
+    use mongodb::bson::{self, doc, Document};
+
+    /// A plot as saved before the ids and the mining areas.
+    fn legacy_plot(object_id: i32, x: i32, current_turtle: Option<&str>) -> Document {
+        doc! {
+            "_id": object_id,
+            "position": { "x": x, "y": 48, "z": -2777 },
+            "mined_depth_segment": 2,
+            "current_turtle": current_turtle,
+        }
+    }
+
+    #[test]
+    fn test_migrate_legacy_plots() {
+        let plots = [
+            legacy_plot(1, -559, None),
+            legacy_plot(2, -553, Some("miner")),
+            legacy_plot(3, -547, None),
+        ];
+        let migration = plan_plot_migration(&plots);
+        assert_eq!(migration.next_id, 3);
+        assert_eq!(migration.next_index, 3);
+        for (index, (object_id, set)) in migration.updates.iter().enumerate() {
+            assert_eq!(object_id, &plots[index].get("_id").cloned().unwrap());
+            let mut plot = plots[index].clone();
+            plot.extend(set.clone());
+            let plot: MiningPlot = bson::from_document(plot).unwrap();
+            assert_eq!(plot.id, index as u32);
+            assert_eq!(plot.index, index as u32);
+            assert_eq!(plot.area, "default");
+            assert_eq!(plot.mined_depth_segment, 2);
+        }
+
+        // Once migrated, only the counters are set again
+        let migrated: Vec<_> = plots
+            .iter()
+            .zip(&migration.updates)
+            .map(|(plot, (_, set))| {
+                let mut plot = plot.clone();
+                plot.extend(set.clone());
+                plot
+            })
+            .collect();
+        let expected = PlotMigration {
+            updates: Vec::new(),
+            next_id: 3,
+            next_index: 3,
+        };
+        assert_eq!(plan_plot_migration(&migrated), expected);
+    }
+
+    #[test]
+    fn test_migrate_plots_without_area() {
+        // Numbered before the areas, and a plot of another area
+        let mut plots = vec![
+            doc! { "_id": 1, "id": 0_i64, "position": { "x": 0, "y": 0, "z": 0 } },
+            doc! { "_id": 2, "id": 1_i64, "position": { "x": 6, "y": 0, "z": 0 } },
+            doc! { "_id": 3, "id": 2_i64, "area": "west", "index": 0_i64 },
+        ];
+        plots.push(legacy_plot(0, 12, None));
+        let migration = plan_plot_migration(&plots);
+        assert_eq!(migration.next_id, 4);
+        assert_eq!(migration.next_index, 4);
+        let sets: Vec<_> = migration.updates.iter().map(|(_, set)| set).collect();
+        assert_eq!(
+            sets,
+            [
+                &doc! { "area": "default", "index": 0 },
+                &doc! { "area": "default", "index": 1 },
+                &doc! { "id": 3, "area": "default", "index": 3 },
+            ]
+        );
+    }
+}
@@ -0,0 +1,451 @@
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+
+use crate::{
+    api,
+    config::Config,
+    inventory::Inventory,
+    locks::TurtleLocks,
+    mining_areas::area_world,
+    order_parser::parse_orders,
+    order_queue::Priority,
+    persistance::{Storage, StorageResult},
+    script::ScriptCache,
+    stations::StationReport,
+    telemetry::{TelemetrySample, TelemetryTopic},
+    turtle::{self, Ack, AckOutcome, Command, CommandName, GpsFix, Turtle},
+    utils::{unix_timestamp, Area},
+    world_map::{Block, Inspection, AIR},
+};
+
+async fn luafile(
+    req: HttpRequest,
+    config: web::Data<Config>,
+    scripts: web::Data<ScriptCache>,
+) -> HttpResponse {
+    let connection = req.connection_info();
+    let api_url = format!("{}://{}", connection.scheme(), connection.host());
+    let script = scripts.get(&config).and_then(|script| {
+        script.render(&api_url).map_err(|error| {
+            log::error!("{}", error);
+            error.to_string()
+        })
+    });
+    match script {
+        Ok(script) => HttpResponse::Ok().body(script),
+        Err(error) => HttpResponse::InternalServerError().body(error),
+    }
+}
+
+#[derive(Deserialize)]
+struct RequestQuery {
+    /// Version of the lua script run by the turtle.
+    version: Option<String>,
+}
+
+#[get("/request/{name}")]
+async fn request(
+    path: web::Path<String>,
+    query: web::Query<RequestQuery>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+    locks: web::Data<TurtleLocks>,
+    scripts: web::Data<ScriptCache>,
+) -> Result<String> {
+    let name = path.into_inner();
+    log::info!("Request received from turtle {}", name);
+    let lock = locks.turtle(&name);
+    let _guard = lock.lock().await;
+    let turtle = storage.find_turtle(&name).await?;
+    if let Some(mut turtle) = turtle {
+        turtle.seen(unix_timestamp());
+        if let Some(version) = &query.version {
+            turtle.telemetry.script_version = Some(version.clone());
+            // An unreadable script is logged by the cache, the turtle keeps its version
+            match scripts.get(&config) {
+                Ok(script) if &script.version != version => {
+                    log::info!(
+                        "Turtle {} runs script {}, updating it to {}",
+                        name,
+                        version,
+                        script.version
+                    );
+                    storage.save_turtle(&turtle).await?;
+                    return Ok(Command::new(CommandName::Update, 0).to_string());
+                }
+                _ => {}
+            }
+        }
+        let result = turtle.orders(storage.as_ref(), &config, &locks).await?;
+        storage.save_turtle(&turtle).await?;
+        return Ok(result);
+    } else {
+        let mut turtle = turtle::Turtle::default(name, &config.world);
+        turtle.telemetry.script_version = query.version.clone();
+        storage.insert_turtle(&turtle).await?;
+    }
+    Ok(String::from("sleep(2)"))
+}
+
+#[derive(Deserialize)]
+struct Info {
+    info: String,
+}
+
+/// The history of a turtle is pruned at most once per interval, its samples can outlive
+/// `telemetry.retention` by as much.
+const PRUNE_INTERVAL: u64 = 3600;
+
+/// Add a value posted by the turtle to its history, and drop the samples past the retention when
+/// they weren't dropped for a while. The turtle must be saved afterwards.
+async fn record_sample(
+    storage: &dyn Storage,
+    config: &Config,
+    turtle: &mut Turtle,
+    topic: String,
+    value: String,
+) -> StorageResult<()> {
+    let time = unix_timestamp();
+    let sample = TelemetrySample {
+        turtle: turtle.name().to_string(),
+        topic,
+        time,
+        value,
+    };
+    storage.add_telemetry_sample(&sample).await?;
+    let interval = PRUNE_INTERVAL.min(config.telemetry.retention);
+    if time >= turtle.telemetry_pruned + interval {
+        let oldest = time.saturating_sub(config.telemetry.retention);
+        storage
+            .delete_telemetry_samples(turtle.name(), oldest)
+            .await?;
+        turtle.telemetry_pruned = time;
+    }
+    Ok(())
+}
+
+/// A telemetry value, rejected with a 422 when malformed, or a custom info kept as it is. Both are
+/// added to the history of the turtle.
+#[post("/info/{name}/{topic}")]
+async fn add_information(
+    path: web::Path<(String, String)>,
+    form: web::Form<Info>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+    locks: web::Data<TurtleLocks>,
+) -> Result<HttpResponse> {
+    let (name, topic) = path.into_inner();
+    log::info!("Info received from turtle {}, topic: {}", name, topic);
+    let lock = locks.turtle(&name);
+    let _guard = lock.lock().await;
+    let mut turtle = match storage.find_turtle(&name).await? {
+        Some(turtle) => turtle,
+        None => return Ok(HttpResponse::Ok().body("Turtle not found")),
+    };
+    let (topic, value) = match TelemetryTopic::from_name(&topic) {
+        Some(topic) => {
+            if let Err(error) = turtle.telemetry.set(topic, &form.info) {
+                log::warn!("Invalid {} for {}: {}", topic, name, error);
+                return Ok(HttpResponse::UnprocessableEntity().body(error));
+            }
+            let value = turtle.telemetry.get(topic).unwrap_or(form.info.clone());
+            (topic.to_string(), value)
+        }
+        None => {
+            turtle.infos.insert(topic.clone(), form.info.clone());
+            (topic, form.info.clone())
+        }
+    };
+    record_sample(storage.as_ref(), &config, &mut turtle, topic, value).await?;
+    storage.save_turtle(&turtle).await?;
+    Ok(HttpResponse::Ok().body("ok"))
+}
+
+/// Content of the 16 slots, posted as JSON by the turtle before requesting orders.
+#[post("/inventory/{name}")]
+async fn report_inventory(
+    path: web::Path<String>,
+    body: web::Json<Inventory>,
+    storage: web::Data<dyn Storage>,
+    locks: web::Data<TurtleLocks>,
+) -> Result<HttpResponse> {
+    let name = path.into_inner();
+    if let Err(error) = body.validate() {
+        log::warn!("Invalid inventory for {}: {}", name, error);
+        return Ok(HttpResponse::BadRequest().body(error));
+    }
+    let lock = locks.turtle(&name);
+    let _guard = lock.lock().await;
+    if storage.set_turtle_inventory(&name, &body).await? {
+        Ok(HttpResponse::Ok().body("ok"))
+    } else {
+        Ok(HttpResponse::Ok().body("Turtle not found"))
+    }
+}
+
+/// Sent by the turtle once it ran a batch, with the number of commands it managed to do and the
+/// blocks it dug, credited to the plot segment mined by the batch.
+#[post("/ack/{name}")]
+async fn acknowledge(
+    path: web::Path<String>,
+    form: web::Form<Ack>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+    locks: web::Data<TurtleLocks>,
+) -> Result<&'static str> {
+    let name = path.into_inner();
+    let lock = locks.turtle(&name);
+    let _guard = lock.lock().await;
+    let mut turtle = match storage.find_turtle(&name).await? {
+        Some(turtle) => turtle,
+        None => return Ok("Turtle not found"),
+    };
+    turtle.seen(unix_timestamp());
+    let mining = turtle.batch.as_ref().and_then(|batch| batch.mining);
+    match turtle.acknowledge(&form) {
+        AckOutcome::Completed => log::info!("Turtle {} completed batch {}", name, form.batch),
+        AckOutcome::Failed(command) => log::warn!(
+            "Turtle {} stopped batch {} at {} after {} steps",
+            name,
+            form.batch,
+            command,
+            form.steps
+        ),
+        AckOutcome::UnknownBatch => {
+            log::warn!("Turtle {} acknowledged unknown batch {}", name, form.batch);
+            return Ok("Unknown batch");
+        }
+    }
+    if let Some(fuel) = form.fuel {
+        let topic = TelemetryTopic::FuelLevel.to_string();
+        record_sample(
+            storage.as_ref(),
+            &config,
+            &mut turtle,
+            topic,
+            fuel.to_string(),
+        )
+        .await?;
+    }
+    storage.save_turtle(&turtle).await?;
+    let dug = form.dug_counts();
+    if let Some(task) = mining.filter(|_| !dug.is_empty()) {
+        if let Some(mut plot) = storage.find_plot(task.plot).await? {
+            plot.add_yields(task.segment, &dug);
+            storage.save_plot(&plot).await?;
+        }
+    }
+    Ok("ok")
+}
+
+/// Position found with `gps.locate()`. Answers `replan` when it doesn't match the tracked
+/// position, the turtle then stops its batch and asks for new orders.
+#[post("/gps/{name}")]
+async fn locate(
+    path: web::Path<String>,
+    form: web::Form<GpsFix>,
+    storage: web::Data<dyn Storage>,
+    locks: web::Data<TurtleLocks>,
+) -> Result<&'static str> {
+    let name = path.into_inner();
+    let lock = locks.turtle(&name);
+    let _guard = lock.lock().await;
+    let mut turtle = match storage.find_turtle(&name).await? {
+        Some(turtle) => turtle,
+        None => return Ok("Turtle not found"),
+    };
+    match turtle.reconcile(&form) {
+        Some(drift) => {
+            log::warn!(
+                "Turtle {} drifted: expected at {:?} facing {}, located at {:?} facing {}",
+                name,
+                drift.expected,
+                drift.expected_direction,
+                drift.actual,
+                turtle.direction
+            );
+            storage.save_turtle(&turtle).await?;
+            Ok("replan")
+        }
+        None => Ok("ok"),
+    }
+}
+
+/// Block seen by an `Inspect` command, added to the world map.
+#[post("/inspect/{name}")]
+async fn inspect(
+    path: web::Path<String>,
+    form: web::Form<Inspection>,
+    storage: web::Data<dyn Storage>,
+) -> Result<&'static str> {
+    let name = path.into_inner();
+    let turtle = match storage.find_turtle(&name).await? {
+        Some(turtle) => turtle,
+        None => return Ok("Turtle not found"),
+    };
+    let block = turtle.inspected_block(&form);
+    log::debug!(
+        "Turtle {} found {} at {:?}",
+        name,
+        block.name,
+        block.position
+    );
+    storage.save_blocks(&[block]).await?;
+    Ok("ok")
+}
+
+/// Ore dug by the turtle. The map now holds air there, and the neighbours not explored yet are
+/// dug next to follow the vein.
+#[post("/dug/{name}")]
+async fn dug_ore(
+    path: web::Path<String>,
+    form: web::Form<Inspection>,
+    storage: web::Data<dyn Storage>,
+    config: web::Data<Config>,
+    locks: web::Data<TurtleLocks>,
+) -> Result<&'static str> {
+    let name = path.into_inner();
+    let lock = locks.turtle(&name);
+    let _guard = lock.lock().await;
+    let turtle = match storage.find_turtle(&name).await? {
+        Some(turtle) => turtle,
+        None => return Ok("Turtle not found"),
+    };
+    let position = turtle.inspected_block(&form).position;
+    log::debug!("Turtle {} dug {} at {:?}", name, form.name, position);
+    storage
+        .save_blocks(&[Block {
+            position,
+            name: AIR.to_string(),
+            updated: unix_timestamp(),
+        }])
+        .await?;
+    if config.world.ores.contains(&form.name) {
+        if let Some(mut plot) = storage.find_turtle_plot(&name).await? {
+            let explored: Vec<_> = storage
+                .find_blocks(&Area::new(position, position).grow(1))
+                .await?
+                .into_iter()
+                .filter(|block| block.name == AIR)
+                .map(|block| block.position)
+                .collect();
+            let world = area_world(storage.as_ref(), &config.world, &plot.area).await?;
+            plot.add_ore(position, |neighbour| explored.contains(neighbour), &world);
+            storage.save_plot(&plot).await?;
+        }
+    }
+    Ok("ok")
+}
+
+/// Capacity of the station chest the turtle just used, so that full chests are skipped.
+#[post("/station/{name}")]
+async fn station_report(
+    path: web::Path<String>,
+    form: web::Form<StationReport>,
+    storage: web::Data<dyn Storage>,
+    locks: web::Data<TurtleLocks>,
+) -> Result<&'static str> {
+    let name = path.into_inner();
+    let lock = locks.turtle(&name);
+    let _guard = lock.lock().await;
+    let turtle = match storage.find_turtle(&name).await? {
+        Some(turtle) => turtle,
+        None => return Ok("Turtle not found"),
+    };
+    let stations = storage.list_stations().await?;
+    match turtle.used_station(&form, &stations) {
+        Some(station) => {
+            log::debug!(
+                "Turtle {} reports a capacity of {} for station {}",
+                name,
+                form.capacity,
+                station.name
+            );
+            storage
+                .set_station_capacity(&station.name, form.capacity)
+                .await?;
+            Ok("ok")
+        }
+        None => Ok("Unknown station"),
+    }
+}
+
+// #[get("/pos/{name}")]
+// async fn get_position(
+//     web::Path(name): web::Path<String>,
+//     storage: web::Data<dyn Storage>,
+// ) -> Result<String> {
+//     let turtle = storage.find_turtle(&name).await?;
+//     if let Some(turtle) = turtle {
+//         Ok(turtle.get_position())
+//     } else {
+//         Ok(String::from("Missing turtle"))
+//     }
+// }
+
+#[get("/info/{name}/{topic}")]
+async fn get_information(
+    path: web::Path<(String, String)>,
+    storage: web::Data<dyn Storage>,
+) -> Result<String> {
+    let (name, topic) = path.into_inner();
+    log::info!("Info received from turtle {}, topic: {}", name, topic);
+    let turtle = storage.find_turtle(&name).await?;
+    if let Some(turtle) = turtle {
+        Ok(turtle.info(&topic).unwrap_or_else(|| String::from("N/A")))
+    } else {
+        Ok(format!("No turtle with name: {} found", name))
+    }
+}
+
+#[derive(Deserialize)]
+struct Orders {
+    orders: String,
+}
+
+/// Orders in the order language, queued after the waiting ones.
+#[post("/order/{name}")]
+async fn add_orders(
+    path: web::Path<String>,
+    form: web::Form<Orders>,
+    storage: web::Data<dyn Storage>,
+    locks: web::Data<TurtleLocks>,
+) -> Result<HttpResponse> {
+    let name = path.into_inner();
+    log::info!("Adding orders for {}", name);
+    let orders = match parse_orders(&form.orders) {
+        Ok(orders) => orders,
+        Err(errors) => {
+            log::warn!("Invalid orders for {}:\n{}", name, errors);
+            return Ok(HttpResponse::BadRequest().body(errors.to_string()));
+        }
+    };
+    let lock = locks.turtle(&name);
+    let _guard = lock.lock().await;
+    match storage.find_turtle(&name).await? {
+        Some(mut turtle) => {
+            turtle.queue.append(orders, Priority::Normal);
+            storage.set_turtle_queue(&name, &turtle.queue).await?;
+            Ok(HttpResponse::Ok().body("ok"))
+        }
+        None => Ok(HttpResponse::Ok().body("Turtle not found")),
+    }
+}
+
+/// Every route of the server, expects a `Data<dyn Storage>`, a `Data<Config>`, a
+/// `Data<TurtleLocks>` and a `Data<ScriptCache>` shared by the workers in the app data.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.route("luafile", web::get().to(luafile))
+        .service(web::scope("/api/v1").configure(api::configure))
+        .service(request)
+        .service(add_orders)
+        .service(add_information)
+        .service(report_inventory)
+        .service(acknowledge)
+        .service(locate)
+        .service(inspect)
+        .service(dug_ore)
+        .service(station_report)
+        .service(get_information);
+    // .service(get_position)
+}
@@ -0,0 +1,213 @@
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::config::Config;
+
+/// Number of hexadecimal characters kept from the hash of the script.
+const VERSION_LENGTH: usize = 12;
+
+/// Error while rendering the lua script served to the turtles.
+#[derive(Debug)]
+pub enum ScriptError {
+    Read(String, std::io::Error),
+    UnknownPlaceholder(String),
+    Unclosed(usize),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Read(path, error) => {
+                write!(f, "Unable to read lua script {}: {}", path, error)
+            }
+            ScriptError::UnknownPlaceholder(name) => {
+                write!(f, "Unknown placeholder {{{{{}}}}} in lua script", name)
+            }
+            ScriptError::Unclosed(offset) => {
+                write!(f, "Unclosed placeholder at byte {} of lua script", offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+/// Replace every `{{name}}` of `template` by its value, unknown names are an error.
+pub fn render(template: &str, values: &HashMap<&str, String>) -> Result<String, ScriptError> {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| ScriptError::Unclosed(template.len() - rest.len() + start))?;
+        let name = rest[start + 2..start + end].trim();
+        let value = values
+            .get(name)
+            .ok_or_else(|| ScriptError::UnknownPlaceholder(name.to_string()))?;
+        result.push_str(value);
+        rest = &rest[start + end + 2..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Lua table with each name as a key, e.g. `{["minecraft:iron_ore"] = true}`.
+fn lua_set(names: &[String]) -> String {
+    let entries: Vec<_> = names
+        .iter()
+        .map(|name| format!("[{:?}] = true", name))
+        .collect();
+    format!("{{{}}}", entries.join(", "))
+}
+
+/// The lua script served to the turtles, with the settings filled in.
+pub struct Script {
+    template: String,
+    values: HashMap<&'static str, String>,
+    /// Hash of the template and of the settings, a turtle running another version is outdated.
+    pub version: String,
+}
+
+impl Script {
+    pub fn load(config: &Config) -> Result<Self, ScriptError> {
+        let path = &config.script.path;
+        let template = std::fs::read_to_string(path)
+            .map_err(|error| ScriptError::Read(path.to_string(), error))?;
+        let mut values = HashMap::from([
+            ("poll_interval", config.script.poll_interval.to_string()),
+            ("fuel_threshold", config.world.min_fuel_level.to_string()),
+            ("use_gps", config.script.gps.to_string()),
+            ("ores", lua_set(&config.world.ores)),
+        ]);
+        if let Some(public_url) = &config.script.public_url {
+            values.insert("api_url", public_url.trim_end_matches('/').to_string());
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(&template);
+        let mut settings: Vec<_> = values.iter().collect();
+        settings.sort();
+        for (name, value) in settings {
+            hasher.update(format!("\n{}={}", name, value));
+        }
+        let version: String = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        values.insert("script_version", version[..VERSION_LENGTH].to_string());
+
+        Ok(Script {
+            template,
+            version: version[..VERSION_LENGTH].to_string(),
+            values,
+        })
+    }
+
+    /// Render the script, `api_url` is used when no public URL is configured.
+    pub fn render(&self, api_url: &str) -> Result<String, ScriptError> {
+        let mut values = self.values.clone();
+        values
+            .entry("api_url")
+            .or_insert_with(|| api_url.to_string());
+        render(&self.template, &values)
+    }
+}
+
+struct LoadedScript {
+    /// Modification time of the file when loaded, `None` when unknown.
+    modified: Option<SystemTime>,
+    script: Result<Arc<Script>, String>,
+}
+
+/// The script of `script.path`, only read and hashed again when the file is modified.
+#[derive(Default)]
+pub struct ScriptCache {
+    loaded: Mutex<Option<LoadedScript>>,
+}
+
+impl ScriptCache {
+    /// The script rendered with the settings of `config`, which don't change while the server
+    /// runs. An error is logged once, until the file is modified.
+    pub fn get(&self, config: &Config) -> Result<Arc<Script>, String> {
+        let modified = std::fs::metadata(&config.script.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let mut loaded = self.loaded.lock().unwrap();
+        match &*loaded {
+            Some(loaded) if loaded.modified == modified => loaded.script.clone(),
+            _ => {
+                let script = Script::load(config).map(Arc::new).map_err(|error| {
+                    log::error!("{}", error);
+                    error.to_string()
+                });
+                *loaded = Some(LoadedScript {
+                    modified,
+                    script: script.clone(),
+                });
+                script
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, Script, ScriptCache, ScriptError};
+    use crate::config::Config;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_render() {
+        let values = HashMap::from([
+            ("api_url", "http://localhost:8787".to_string()),
+            ("poll_interval", "2".to_string()),
+        ]);
+        let script = render(
+            "api_url = \"{{api_url}}\"\nsleep({{ poll_interval }})\nlocal t = {}",
+            &values,
+        )
+        .unwrap();
+        assert_eq!(
+            script,
+            "api_url = \"http://localhost:8787\"\nsleep(2)\nlocal t = {}"
+        );
+        assert!(matches!(
+            render("{{missing}}", &values),
+            Err(ScriptError::UnknownPlaceholder(name)) if name == "missing"
+        ));
+        assert!(matches!(
+            render("ok {{api_url", &values),
+            Err(ScriptError::Unclosed(3))
+        ));
+    }
+
+    #[test]
+    fn test_script_version() {
+        let mut config = Config::default();
+        config.script.path = concat!(env!("CARGO_MANIFEST_DIR"), "/main.lua").to_string();
+        let script = Script::load(&config).unwrap();
+        assert_eq!(script.version.len(), 12);
+        assert_eq!(script.version, Script::load(&config).unwrap().version);
+        let rendered = script.render("http://localhost:8787").unwrap();
+        assert!(rendered.contains(&format!("script_version = \"{}\"", script.version)));
+
+        config.script.poll_interval += 1;
+        assert_ne!(script.version, Script::load(&config).unwrap().version);
+
+        let cache = ScriptCache::default();
+        let cached = cache.get(&config).unwrap();
+        assert!(std::sync::Arc::ptr_eq(
+            &cached,
+            &cache.get(&config).unwrap()
+        ));
+        config.script.path = "missing.lua".to_string();
+        assert!(ScriptCache::default().get(&config).is_err());
+    }
+}
@@ -0,0 +1,505 @@
+//! Offline turtle, executing the lines sent by the server the way `main.lua` does.
+//!
+//! The simulated turtle runs in a [`World`] made of blocks, uses fuel to move and keeps what it
+//! digs in its 16 slots. It allows to test the server end-to-end without a Minecraft server.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    inventory::Inventory,
+    stations::StationReport,
+    turtle::{Ack, GpsFix},
+    utils::{Direction, Position},
+    world_map::{Inspection, Side, AIR},
+};
+
+mod world;
+
+pub use crate::inventory::{ItemStack, FUEL_SLOT, INVENTORY_SIZE, STACK_SIZE};
+pub use world::{World, BEDROCK, CHEST, STONE};
+
+pub const COAL: &str = "minecraft:coal";
+const COAL_FUEL_VALUE: i32 = 80;
+const FUEL_LIMIT: i32 = 20000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimulationError {
+    InvalidLine(String),
+    UnknownCommand(String),
+    /// A move failed after the given number of steps.
+    Stopped(String, i32),
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimulationError::InvalidLine(line) => write!(f, "Unable to parse line: {}", line),
+            SimulationError::UnknownCommand(name) => write!(f, "Unknown command: {}", name),
+            SimulationError::Stopped(line, steps) => {
+                write!(f, "Stopped {} after {} steps", line, steps)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+#[derive(Debug, Clone)]
+pub struct SimulatedTurtle {
+    pub pos: Position,
+    pub direction: Direction,
+    pub fuel: i32,
+    pub inventory: Vec<Option<ItemStack>>,
+    /// Infos posted by the lua script while running commands, e.g. `("stuck", "up")`.
+    reports: Vec<(String, String)>,
+    /// Positions reported by `Locate` while running a batch.
+    fixes: Vec<GpsFix>,
+    /// Blocks reported by the `Inspect` commands while running a batch.
+    inspections: Vec<Inspection>,
+    /// Blocks reported when dug, the `ores` of the lua script.
+    pub ores: Vec<String>,
+    /// Blocks dug during the batch, sent with the acknowledgement.
+    dug: HashMap<String, u32>,
+    /// Ores dug while running a batch.
+    dug_ores: Vec<Inspection>,
+    /// Capacities of the chests used by `DepositItem` and `Refuel`.
+    station_reports: Vec<StationReport>,
+    /// Batch being run with the number of its commands done, and steps of the current one.
+    running: Option<(u32, usize)>,
+    steps: i32,
+}
+
+enum Vertical {
+    Up,
+    Down,
+}
+
+impl SimulatedTurtle {
+    pub fn new(pos: Position, direction: Direction, fuel: i32) -> Self {
+        SimulatedTurtle {
+            pos,
+            direction,
+            fuel,
+            inventory: vec![None; INVENTORY_SIZE],
+            reports: Vec::new(),
+            fixes: Vec::new(),
+            inspections: Vec::new(),
+            ores: Vec::new(),
+            dug: HashMap::new(),
+            dug_ores: Vec::new(),
+            station_reports: Vec::new(),
+            running: None,
+            steps: 0,
+        }
+    }
+
+    /// Add items to the inventory, e.g. coal in the [`FUEL_SLOT`].
+    pub fn give(&mut self, slot: usize, name: &str, count: u32) {
+        self.inventory[slot] = Some(ItemStack {
+            name: name.to_string(),
+            count,
+        });
+    }
+
+    /// Number of items of a kind in the inventory.
+    pub fn count(&self, name: &str) -> u32 {
+        self.inventory
+            .iter()
+            .flatten()
+            .filter(|stack| stack.name == name)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    /// Infos to post before requesting orders, like the main loop of `main.lua`.
+    pub fn cycle_infos(&mut self, fuel_threshold: i32) -> Vec<(String, String)> {
+        vec![
+            (
+                "fuel_level".to_string(),
+                self.refuel_check(fuel_threshold).to_string(),
+            ),
+            ("fuel_limit".to_string(), FUEL_LIMIT.to_string()),
+        ]
+    }
+
+    /// What is posted to `/inventory` before requesting orders.
+    pub fn report_inventory(&self) -> Inventory {
+        Inventory {
+            slots: self.inventory.clone(),
+        }
+    }
+
+    /// Infos posted while running the last commands.
+    pub fn take_reports(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.reports)
+    }
+
+    /// GPS fixes of the `Locate` commands of the last batch. Unlike the lua script, the batch
+    /// goes on without waiting for the answer of the server.
+    pub fn take_fixes(&mut self) -> Vec<GpsFix> {
+        std::mem::take(&mut self.fixes)
+    }
+
+    pub fn take_inspections(&mut self) -> Vec<Inspection> {
+        std::mem::take(&mut self.inspections)
+    }
+
+    /// Ores dug during the last batch, in the order they were dug.
+    pub fn take_dug_ores(&mut self) -> Vec<Inspection> {
+        std::mem::take(&mut self.dug_ores)
+    }
+
+    /// Capacities of the station chests used during the last batch.
+    pub fn take_station_reports(&mut self) -> Vec<StationReport> {
+        std::mem::take(&mut self.station_reports)
+    }
+
+    /// What `Locate` sends, `batch` is the batch being run and the number of its commands done.
+    pub fn locate(&self, heading: bool, batch: Option<(u32, usize)>) -> GpsFix {
+        GpsFix {
+            x: self.pos.x,
+            y: self.pos.y,
+            z: self.pos.z,
+            heading: heading.then_some(self.direction),
+            batch: batch.map(|(id, _)| id),
+            done: batch.map(|(_, done)| done),
+        }
+    }
+
+    /// Refuel from the last slot when under the threshold, returns the fuel level before refueling.
+    pub fn refuel_check(&mut self, fuel_threshold: i32) -> i32 {
+        let fuel_level = self.fuel;
+        if fuel_level < fuel_threshold {
+            if let Some(stack) = &mut self.inventory[FUEL_SLOT] {
+                let amount = stack.count.saturating_sub(1);
+                if stack.name == COAL && amount > 0 {
+                    stack.count -= amount;
+                    self.fuel = (self.fuel + amount as i32 * COAL_FUEL_VALUE).min(FUEL_LIMIT);
+                }
+            }
+        }
+        fuel_level
+    }
+
+    /// Run every line of a response from the server, stopping at the first one that fails.
+    pub fn run(&mut self, world: &mut World, response: &str) -> Result<(), SimulationError> {
+        for line in response.lines() {
+            self.execute(world, line)?;
+        }
+        Ok(())
+    }
+
+    /// Run a response like the main loop of `main.lua`, returns the acknowledgement to send when
+    /// it starts with a `Batch(id)` line.
+    pub fn respond(&mut self, world: &mut World, response: &str) -> Option<Ack> {
+        let mut lines = response.lines().peekable();
+        let batch = lines
+            .next_if(|line| line.starts_with("Batch("))
+            .and_then(|line| line["Batch(".len()..].strip_suffix(')')?.parse().ok());
+        let mut done = 0;
+        let mut steps = 0;
+        for line in lines {
+            let line = line.trim();
+            if let Some(heading) = line.strip_prefix("Locate(") {
+                let fix = self.locate(heading == "1)", batch.map(|batch| (batch, done)));
+                self.fixes.push(fix);
+            }
+            let side = match line.split('(').next() {
+                Some("Inspect") => Some(Side::Front),
+                Some("InspectUp") => Some(Side::Up),
+                Some("InspectDown") => Some(Side::Down),
+                _ => None,
+            };
+            if let Some(side) = side {
+                let position = self.pos + side.offset(self.direction);
+                self.inspections.push(Inspection {
+                    side,
+                    name: world.block(&position).unwrap_or(AIR).to_string(),
+                    batch,
+                    done: batch.map(|_| done),
+                    steps: 0,
+                });
+            }
+            self.running = batch.map(|batch| (batch, done));
+            match self.execute(world, line) {
+                Ok(()) => done += 1,
+                Err(error) => {
+                    if let SimulationError::Stopped(_, stopped_after) = error {
+                        steps = stopped_after;
+                    }
+                    break;
+                }
+            }
+        }
+        self.running = None;
+        let mut dug: Vec<_> = self
+            .dug
+            .drain()
+            .map(|(name, count)| format!("{}*{}", name, count))
+            .collect();
+        dug.sort();
+        batch.map(|batch| Ack {
+            batch,
+            done,
+            steps,
+            fuel: Some(self.fuel),
+            dug: dug.join(","),
+        })
+    }
+
+    /// Run a line like `Forward(3)`.
+    pub fn execute(&mut self, world: &mut World, line: &str) -> Result<(), SimulationError> {
+        let invalid_line = || SimulationError::InvalidLine(line.to_string());
+        let (name, argument) = line
+            .trim()
+            .strip_suffix(')')
+            .and_then(|call| call.split_once('('))
+            .ok_or_else(invalid_line)?;
+        let argument: i32 = if argument.is_empty() {
+            0
+        } else {
+            argument.trim().parse().map_err(|_| invalid_line())?
+        };
+        let stopped = |steps| Err(SimulationError::Stopped(line.to_string(), steps));
+        match name {
+            "Up" | "Down" => {
+                let vertical = if name == "Up" {
+                    Vertical::Up
+                } else {
+                    Vertical::Down
+                };
+                for step in 0..argument {
+                    self.steps = step;
+                    if !self.vertical(world, &vertical) {
+                        return stopped(step);
+                    }
+                }
+            }
+            "Left" => self.direction = self.direction.turn_left(argument),
+            "Right" => self.direction = self.direction.turn_right(argument),
+            "Forward" => {
+                for step in 0..argument {
+                    self.steps = step;
+                    if !self.forward(world) {
+                        return stopped(step);
+                    }
+                }
+            }
+            "ForwardDig" | "ForwardDigUp" => {
+                for step in 0..argument {
+                    self.steps = step;
+                    self.dig(world, self.pos + Position { x: 0, y: 1, z: 0 });
+                    if name == "ForwardDig" {
+                        self.dig(world, self.pos + Position { x: 0, y: -1, z: 0 });
+                    }
+                    if !self.forward(world) {
+                        return stopped(step);
+                    }
+                }
+            }
+            "DepositItem" => self.deposit(world),
+            "Refuel" => self.refuel(world),
+            "RefuelCheck" => {
+                self.refuel_check(i32::MAX);
+            }
+            "Batch" | "Locate" | "Inspect" | "InspectUp" | "InspectDown" | "Sleep" | "sleep"
+            | "Reboot" | "Update" => {}
+            _ => return Err(SimulationError::UnknownCommand(name.to_string())),
+        }
+        Ok(())
+    }
+
+    fn front(&self) -> Position {
+        self.pos + self.direction.forward_vector()
+    }
+
+    /// Dig a block and keep what it drops, returns `false` if there is a block that can't be dug.
+    fn dig(&mut self, world: &mut World, position: Position) -> bool {
+        if world.is_air(&position) {
+            return true;
+        }
+        match world.dig(&position) {
+            Some(name) => {
+                // What doesn't fit in the inventory is dropped on the floor
+                world::insert_items(&mut self.inventory, &name, 1);
+                *self.dug.entry(name.clone()).or_default() += 1;
+                if self.ores.contains(&name) {
+                    let side = match position.y - self.pos.y {
+                        1 => Side::Up,
+                        -1 => Side::Down,
+                        _ => Side::Front,
+                    };
+                    self.dug_ores.push(Inspection {
+                        side,
+                        name,
+                        batch: self.running.map(|(batch, _)| batch),
+                        done: self.running.map(|(_, done)| done),
+                        steps: self.steps,
+                    });
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `false` when the turtle couldn't move, like `turtle.forward()`.
+    fn move_to(&mut self, world: &World, position: Position) -> bool {
+        if self.fuel > 0 && world.is_air(&position) {
+            self.fuel -= 1;
+            self.pos = position;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn forward(&mut self, world: &mut World) -> bool {
+        let front = self.front();
+        if !self.dig(world, front) {
+            self.reports
+                .push(("stuck".to_string(), "forward".to_string()));
+        }
+        self.move_to(world, front)
+    }
+
+    fn vertical(&mut self, world: &mut World, vertical: &Vertical) -> bool {
+        let (offset, report) = match vertical {
+            Vertical::Up => (1, "up"),
+            Vertical::Down => (-1, "down"),
+        };
+        let target = self.pos
+            + Position {
+                x: 0,
+                y: offset,
+                z: 0,
+            };
+        if !self.dig(world, target) {
+            self.reports.push(("stuck".to_string(), report.to_string()));
+        }
+        self.move_to(world, target)
+    }
+
+    fn deposit(&mut self, world: &mut World) {
+        let front = self.front();
+        if world.block(&front) != Some(CHEST) {
+            self.reports
+                .push(("last_error".to_string(), "missing chest".to_string()));
+            return;
+        }
+        for slot in self.inventory[..FUEL_SLOT].iter_mut() {
+            if let Some(stack) = slot.take() {
+                let left = world.store(&front, &stack.name, stack.count);
+                if left > 0 {
+                    *slot = Some(ItemStack {
+                        name: stack.name,
+                        count: left,
+                    });
+                }
+            }
+        }
+        if let Some(free_slots) = world.free_slots(&front) {
+            self.report_station(free_slots);
+        }
+    }
+
+    /// Fill the fuel slot with coal from the chest in front, then refuel.
+    fn refuel(&mut self, world: &mut World) {
+        let front = self.front();
+        let held = match &self.inventory[FUEL_SLOT] {
+            Some(stack) if stack.name != COAL => None,
+            Some(stack) => Some(stack.count),
+            None => Some(0),
+        };
+        if let Some(held) = held {
+            let taken = world.take(&front, COAL, STACK_SIZE - held);
+            if taken > 0 {
+                self.give(FUEL_SLOT, COAL, held + taken);
+            }
+        }
+        self.refuel_check(i32::MAX);
+        if world.block(&front) == Some(CHEST) {
+            let left = world.chest_content(&front).values().sum();
+            self.report_station(left);
+        }
+    }
+
+    fn report_station(&mut self, capacity: u32) {
+        self.station_reports.push(StationReport {
+            capacity,
+            batch: self.running.map(|(batch, _)| batch),
+            done: self.running.map(|(_, done)| done),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SimulatedTurtle, World, BEDROCK, CHEST, COAL, FUEL_SLOT, STONE};
+    use crate::utils::{Direction, Position};
+
+    #[test]
+    fn test_simulated_turtle() {
+        let origin = Position { x: 0, y: 0, z: 0 };
+        let mut world = World::default();
+        world.fill(
+            Position {
+                x: -2,
+                y: -3,
+                z: -5,
+            },
+            Position { x: 2, y: -1, z: 0 },
+            STONE,
+        );
+        world.fill(
+            Position {
+                x: -2,
+                y: -4,
+                z: -5,
+            },
+            Position { x: 2, y: -4, z: 0 },
+            BEDROCK,
+        );
+        world.place_chest(Position { x: -1, y: 0, z: 0 });
+        let mut turtle = SimulatedTurtle::new(origin, Direction::North, 10);
+        turtle.give(FUEL_SLOT, COAL, 3);
+
+        // Stuck on bedrock after going down 3 blocks
+        let ack = turtle
+            .respond(&mut world, "Batch(4)\nDown(5)\nForwardDig(2)\nRight(1)")
+            .unwrap();
+        assert_eq!((ack.batch, ack.done, ack.steps), (4, 0, 3));
+        assert_eq!(turtle.pos, Position { x: 0, y: -3, z: 0 });
+        assert_eq!(turtle.take_reports().len(), 1);
+        turtle.run(&mut world, "ForwardDig(2)\nRight(1)").unwrap();
+        assert_eq!(turtle.pos, Position { x: 0, y: -3, z: -2 });
+        assert_eq!(turtle.direction, Direction::East);
+        assert_eq!(turtle.fuel, 5);
+        assert!(world.is_air(&Position { x: 0, y: -2, z: -1 }));
+        assert_eq!(turtle.count(STONE), 6);
+
+        turtle
+            .run(&mut world, "Up(3)\nLeft(3)\nForward(2)")
+            .unwrap();
+        assert_eq!(turtle.pos, origin);
+        assert_eq!(turtle.direction, Direction::South);
+        assert_eq!(turtle.fuel, 0);
+        let infos = turtle.cycle_infos(500);
+        assert_eq!(infos[0].1, "0");
+        assert_eq!(turtle.report_inventory().free_slots(), 14);
+        assert_eq!(turtle.fuel, 160);
+
+        turtle.run(&mut world, "Right(1)\nDepositItem(1)").unwrap();
+        assert_eq!(world.block(&turtle.front()), Some(CHEST));
+        assert_eq!(world.chest_content(&turtle.front())[STONE], 8);
+        assert_eq!(turtle.count(STONE), 0);
+        assert_eq!(turtle.take_station_reports()[0].capacity, 26);
+
+        world.store(&turtle.front(), COAL, 2);
+        turtle.run(&mut world, "Refuel(1)").unwrap();
+        assert_eq!(turtle.fuel, 320);
+        assert_eq!(turtle.count(COAL), 1);
+        assert_eq!(turtle.take_station_reports()[0].capacity, 8);
+        assert!(turtle.execute(&mut world, "Fly(1)").is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use crate::{
+    inventory::{ItemStack, STACK_SIZE},
+    utils::Position,
+};
+
+pub const BEDROCK: &str = "minecraft:bedrock";
+pub const CHEST: &str = "minecraft:chest";
+pub const STONE: &str = "minecraft:stone";
+
+/// Number of stacks a chest can hold.
+const CHEST_SLOTS: usize = 27;
+
+/// Add `count` items to `slots`, filling existing stacks first then empty slots.
+/// Returns the number of items that didn't fit.
+pub(crate) fn insert_items(slots: &mut [Option<ItemStack>], name: &str, mut count: u32) -> u32 {
+    for stack in slots.iter_mut().flatten() {
+        if stack.name == name && stack.count < STACK_SIZE {
+            let added = count.min(STACK_SIZE - stack.count);
+            stack.count += added;
+            count -= added;
+        }
+    }
+    for slot in slots.iter_mut().filter(|slot| slot.is_none()) {
+        if count == 0 {
+            break;
+        }
+        let added = count.min(STACK_SIZE);
+        *slot = Some(ItemStack {
+            name: name.to_string(),
+            count: added,
+        });
+        count -= added;
+    }
+    count
+}
+
+/// A voxel world, every position without a block is air.
+#[derive(Debug, Clone, Default)]
+pub struct World {
+    blocks: HashMap<Position, String>,
+    chests: HashMap<Position, Vec<Option<ItemStack>>>,
+}
+
+impl World {
+    pub fn block(&self, position: &Position) -> Option<&str> {
+        self.blocks.get(position).map(String::as_str)
+    }
+
+    pub fn is_air(&self, position: &Position) -> bool {
+        !self.blocks.contains_key(position)
+    }
+
+    pub fn set_block(&mut self, position: Position, name: &str) {
+        self.chests.remove(&position);
+        self.blocks.insert(position, name.to_string());
+    }
+
+    /// Fill every position between the two corners, included.
+    pub fn fill(&mut self, from: Position, to: Position, name: &str) {
+        for x in from.x.min(to.x)..=from.x.max(to.x) {
+            for y in from.y.min(to.y)..=from.y.max(to.y) {
+                for z in from.z.min(to.z)..=from.z.max(to.z) {
+                    self.set_block(Position { x, y, z }, name);
+                }
+            }
+        }
+    }
+
+    pub fn place_chest(&mut self, position: Position) {
+        self.set_block(position, CHEST);
+        self.chests.insert(position, vec![None; CHEST_SLOTS]);
+    }
+
+    /// Remove a block, returning its name. Bedrock can't be dug.
+    pub fn dig(&mut self, position: &Position) -> Option<String> {
+        if self.block(position) == Some(BEDROCK) {
+            return None;
+        }
+        self.chests.remove(position);
+        self.blocks.remove(position)
+    }
+
+    /// Put items in the chest at `position`, returns the number of items that didn't fit.
+    pub fn store(&mut self, position: &Position, name: &str, count: u32) -> u32 {
+        match self.chests.get_mut(position) {
+            Some(slots) => insert_items(slots, name, count),
+            None => count,
+        }
+    }
+
+    /// Take up to `count` items of a kind from the chest at `position`, returns the number taken.
+    pub fn take(&mut self, position: &Position, name: &str, count: u32) -> u32 {
+        let mut taken = 0;
+        for slot in self.chests.get_mut(position).into_iter().flatten() {
+            if let Some(stack) = slot.as_mut().filter(|stack| stack.name == name) {
+                let amount = stack.count.min(count - taken);
+                stack.count -= amount;
+                taken += amount;
+                if stack.count == 0 {
+                    *slot = None;
+                }
+            }
+        }
+        taken
+    }
+
+    /// Number of empty slots of the chest at `position`, `None` without a chest.
+    pub fn free_slots(&self, position: &Position) -> Option<u32> {
+        let slots = self.chests.get(position)?;
+        Some(slots.iter().filter(|slot| slot.is_none()).count() as u32)
+    }
+
+    /// Number of items of each kind in the chest at `position`.
+    pub fn chest_content(&self, position: &Position) -> HashMap<String, u32> {
+        let mut content = HashMap::new();
+        for stack in self.chests.get(position).into_iter().flatten().flatten() {
+            *content.entry(stack.name.clone()).or_default() += stack.count;
+        }
+        content
+    }
+}
@@ -0,0 +1,101 @@
+//! Chests where the turtles drop off what they dug and take fuel.
+//!
+//! The stations are managed through the API. The turtles report how much room is left in a chest
+//! each time they use it, so that full deposit chests and empty fuel chests are skipped.
+
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+
+use crate::utils::{Direction, Position};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, Serialize, Deserialize)]
+pub enum StationKind {
+    /// A chest where the turtles drop off what they dug, with `DepositItem`.
+    Deposit,
+    /// A chest holding fuel, taken with `Refuel`.
+    Fuel,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Station {
+    pub name: String,
+    pub kind: StationKind,
+    /// Where the turtle stands to use the chest.
+    pub position: Position,
+    /// Direction the turtle faces to use the chest.
+    pub facing: Direction,
+    /// Free slots of a deposit chest, or fuel items left in a fuel chest, as reported by the last
+    /// turtle that used it. Unknown until then.
+    #[serde(default)]
+    pub capacity: Option<u32>,
+}
+
+impl Station {
+    pub fn chest(&self) -> Position {
+        self.position + self.facing.forward_vector()
+    }
+
+    /// Whether the chest can take items or still has fuel, as far as the server knows.
+    pub fn is_usable(&self) -> bool {
+        self.capacity != Some(0)
+    }
+}
+
+/// Sent by `DepositItem` and `Refuel` with the capacity of the chest in front of the turtle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StationReport {
+    pub capacity: u32,
+    /// Batch being run and number of its commands done, to find where the turtle is.
+    pub batch: Option<u32>,
+    pub done: Option<usize>,
+}
+
+/// The usable station of a kind nearest to `position`.
+pub fn nearest_station(
+    stations: &[Station],
+    kind: StationKind,
+    position: Position,
+) -> Option<&Station> {
+    stations
+        .iter()
+        .filter(|station| station.kind == kind && station.is_usable())
+        .min_by_key(|station| {
+            let diff = station.position - position;
+            diff.x.abs() + diff.y.abs() + diff.z.abs()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nearest_station, Station, StationKind};
+    use crate::utils::{Direction, Position};
+
+    fn station(name: &str, kind: StationKind, x: i32, capacity: Option<u32>) -> Station {
+        Station {
+            name: name.to_string(),
+            kind,
+            position: Position { x, y: 64, z: 0 },
+            facing: Direction::East,
+            capacity,
+        }
+    }
+
+    #[test]
+    fn test_nearest_station() {
+        let stations = vec![
+            station("fuel", StationKind::Fuel, 1, None),
+            station("full", StationKind::Deposit, 2, Some(0)),
+            station("near", StationKind::Deposit, 5, Some(3)),
+            station("far", StationKind::Deposit, 20, None),
+        ];
+        let origin = Position { x: 0, y: 64, z: 0 };
+        let name = |kind| nearest_station(&stations, kind, origin).map(|s| s.name.as_str());
+        assert_eq!(name(StationKind::Deposit), Some("near"));
+        assert_eq!(name(StationKind::Fuel), Some("fuel"));
+        assert_eq!(
+            nearest_station(&stations[..2], StationKind::Deposit, origin),
+            None
+        );
+        assert_eq!(stations[0].chest(), Position { x: 2, y: 64, z: 0 });
+    }
+}
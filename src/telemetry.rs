@@ -0,0 +1,291 @@
+//! Values the turtles post about themselves to `/info/{name}/{topic}`.
+//!
+//! The known topics are parsed into [`Telemetry`] and rejected when malformed, the other topics
+//! are kept as they are in the `infos` of the turtle. Every value, on any topic, is also kept as a
+//! [`TelemetrySample`] for `telemetry.retention` seconds, to follow it over time.
+
+use serde::{Deserialize, Serialize};
+use strum_macros::Display;
+
+use crate::inventory::INVENTORY_SIZE;
+
+/// Value given by `turtle.getFuelLevel()` when fuel is disabled on the server.
+const UNLIMITED_FUEL: &str = "unlimited";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum TelemetryTopic {
+    FuelLevel,
+    FuelLimit,
+    IsFull,
+    SelectedSlot,
+    ScriptVersion,
+    LastError,
+}
+
+impl TelemetryTopic {
+    pub const ALL: [TelemetryTopic; 6] = [
+        TelemetryTopic::FuelLevel,
+        TelemetryTopic::FuelLimit,
+        TelemetryTopic::IsFull,
+        TelemetryTopic::SelectedSlot,
+        TelemetryTopic::ScriptVersion,
+        TelemetryTopic::LastError,
+    ];
+
+    /// The topic posted as `name`, whatever its case and underscores, e.g. `fuellevel`,
+    /// `fuel_level` or `fuelLevel`. `issue` is the last error.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name: String = name
+            .chars()
+            .filter(|c| *c != '_')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        match name.as_str() {
+            "fuellevel" => Some(TelemetryTopic::FuelLevel),
+            "fuellimit" => Some(TelemetryTopic::FuelLimit),
+            "isfull" => Some(TelemetryTopic::IsFull),
+            "selectedslot" => Some(TelemetryTopic::SelectedSlot),
+            "scriptversion" => Some(TelemetryTopic::ScriptVersion),
+            "lasterror" | "issue" => Some(TelemetryTopic::LastError),
+            _ => None,
+        }
+    }
+}
+
+/// Last known state of a turtle, `None` until reported.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Telemetry {
+    /// `None` as well when fuel is unlimited.
+    pub fuel_level: Option<i32>,
+    pub fuel_limit: Option<i32>,
+    /// Every slot but the fuel one holds something, posted by the scripts that don't report
+    /// their inventory.
+    pub is_full: Option<bool>,
+    /// From 1 to 16, like in lua.
+    pub selected_slot: Option<u8>,
+    pub script_version: Option<String>,
+    pub last_error: Option<String>,
+}
+
+fn parse_fuel(value: &str) -> Result<Option<i32>, String> {
+    if value == UNLIMITED_FUEL {
+        return Ok(None);
+    }
+    match value.parse::<i32>() {
+        Ok(fuel) if fuel >= 0 => Ok(Some(fuel)),
+        _ => Err(format!(
+            "expected a non-negative number or {}, got {:?}",
+            UNLIMITED_FUEL, value
+        )),
+    }
+}
+
+impl Telemetry {
+    /// Parse and store the value posted for a topic.
+    pub fn set(&mut self, topic: TelemetryTopic, value: &str) -> Result<(), String> {
+        match topic {
+            TelemetryTopic::FuelLevel => self.fuel_level = parse_fuel(value)?,
+            TelemetryTopic::FuelLimit => self.fuel_limit = parse_fuel(value)?,
+            TelemetryTopic::IsFull => {
+                let is_full = value
+                    .parse()
+                    .map_err(|_| format!("expected true or false, got {:?}", value))?;
+                self.is_full = Some(is_full);
+            }
+            TelemetryTopic::SelectedSlot => match value.parse::<u8>() {
+                Ok(slot) if (1..=INVENTORY_SIZE as u8).contains(&slot) => {
+                    self.selected_slot = Some(slot)
+                }
+                _ => {
+                    return Err(format!(
+                        "expected a slot from 1 to {}, got {:?}",
+                        INVENTORY_SIZE, value
+                    ))
+                }
+            },
+            TelemetryTopic::ScriptVersion if value.is_empty() => {
+                return Err("the script version must not be empty".to_string())
+            }
+            TelemetryTopic::ScriptVersion => self.script_version = Some(value.to_string()),
+            TelemetryTopic::LastError => self.last_error = Some(value.to_string()),
+        }
+        Ok(())
+    }
+
+    /// The value of a topic as the turtle posts it.
+    pub fn get(&self, topic: TelemetryTopic) -> Option<String> {
+        match topic {
+            TelemetryTopic::FuelLevel => self.fuel_level.map(|fuel| fuel.to_string()),
+            TelemetryTopic::FuelLimit => self.fuel_limit.map(|fuel| fuel.to_string()),
+            TelemetryTopic::IsFull => self.is_full.map(|is_full| is_full.to_string()),
+            TelemetryTopic::SelectedSlot => self.selected_slot.map(|slot| slot.to_string()),
+            TelemetryTopic::ScriptVersion => self.script_version.clone(),
+            TelemetryTopic::LastError => self.last_error.clone(),
+        }
+    }
+}
+
+/// A value posted by a turtle, `unlimited` for the fuel when it is disabled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetrySample {
+    pub turtle: String,
+    /// Name of a known topic, e.g. `fuel_level`, or a custom topic as posted.
+    pub topic: String,
+    /// Unix timestamp of the post.
+    pub time: u64,
+    pub value: String,
+}
+
+impl TelemetrySample {
+    /// The value as a number, `1` and `0` for `true` and `false`. `None` for the text topics and
+    /// unlimited fuel.
+    pub fn number(&self) -> Option<f64> {
+        match self.value.as_str() {
+            "true" => Some(1.0),
+            "false" => Some(0.0),
+            value => value.parse().ok().filter(|number: &f64| number.is_finite()),
+        }
+    }
+}
+
+/// The samples of a period, or a single sample when not downsampled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryPoint {
+    /// Start of the period, or time of the sample.
+    pub time: u64,
+    pub count: usize,
+    /// Over the numeric values of the period, `None` without any.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    /// Value of the latest sample of the period.
+    pub last: String,
+}
+
+impl TelemetryPoint {
+    fn new(time: u64, samples: &[TelemetrySample]) -> Self {
+        let numbers: Vec<f64> = samples.iter().filter_map(TelemetrySample::number).collect();
+        let mean = if numbers.is_empty() {
+            None
+        } else {
+            Some(numbers.iter().sum::<f64>() / numbers.len() as f64)
+        };
+        TelemetryPoint {
+            time,
+            count: samples.len(),
+            min: numbers.iter().copied().reduce(f64::min),
+            max: numbers.iter().copied().reduce(f64::max),
+            mean,
+            last: samples
+                .last()
+                .map(|sample| sample.value.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Group the samples, sorted by time, in periods of `step` seconds starting at `from`. The periods
+/// without samples are skipped. Each sample is its own point when `step` is `None`.
+pub fn downsample(
+    samples: &[TelemetrySample],
+    from: u64,
+    step: Option<u64>,
+) -> Vec<TelemetryPoint> {
+    let step = match step {
+        Some(step) => step.max(1),
+        None => {
+            return samples
+                .iter()
+                .map(|sample| TelemetryPoint::new(sample.time, std::slice::from_ref(sample)))
+                .collect()
+        }
+    };
+    let period = |sample: &TelemetrySample| sample.time.saturating_sub(from) / step;
+    samples
+        .chunk_by(|a, b| period(a) == period(b))
+        .map(|chunk| TelemetryPoint::new(from + period(&chunk[0]) * step, chunk))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{downsample, Telemetry, TelemetrySample, TelemetryTopic};
+
+    #[test]
+    fn test_topic_names() {
+        for name in ["fuellevel", "fuel_level", "fuelLevel", "FUEL_LEVEL"] {
+            assert_eq!(
+                TelemetryTopic::from_name(name),
+                Some(TelemetryTopic::FuelLevel)
+            );
+        }
+        assert_eq!(
+            TelemetryTopic::from_name("issue"),
+            Some(TelemetryTopic::LastError)
+        );
+        assert_eq!(TelemetryTopic::from_name("stuck"), None);
+        for topic in TelemetryTopic::ALL {
+            assert_eq!(TelemetryTopic::from_name(&topic.to_string()), Some(topic));
+        }
+    }
+
+    #[test]
+    fn test_set_telemetry() {
+        let mut telemetry = Telemetry::default();
+        telemetry.set(TelemetryTopic::FuelLevel, "120").unwrap();
+        telemetry.set(TelemetryTopic::IsFull, "true").unwrap();
+        telemetry.set(TelemetryTopic::SelectedSlot, "16").unwrap();
+        assert_eq!(telemetry.fuel_level, Some(120));
+        assert_eq!(telemetry.is_full, Some(true));
+        assert_eq!(telemetry.get(TelemetryTopic::SelectedSlot).unwrap(), "16");
+
+        for (topic, value) in [
+            (TelemetryTopic::FuelLevel, "lots"),
+            (TelemetryTopic::FuelLimit, "-1"),
+            (TelemetryTopic::IsFull, "yes"),
+            (TelemetryTopic::SelectedSlot, "17"),
+            (TelemetryTopic::ScriptVersion, ""),
+        ] {
+            assert!(telemetry.set(topic, value).is_err(), "{} {}", topic, value);
+        }
+        assert_eq!(telemetry.fuel_level, Some(120));
+        telemetry
+            .set(TelemetryTopic::FuelLevel, "unlimited")
+            .unwrap();
+        assert_eq!(telemetry.fuel_level, None);
+    }
+
+    #[test]
+    fn test_downsample() {
+        let sample = |time, value: &str| TelemetrySample {
+            turtle: "test".to_string(),
+            topic: TelemetryTopic::FuelLevel.to_string(),
+            time,
+            value: value.to_string(),
+        };
+        let samples = vec![
+            sample(1000, "300"),
+            sample(1030, "200"),
+            sample(1059, "250"),
+            sample(1200, "unlimited"),
+        ];
+        let points = downsample(&samples, 1000, Some(60));
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].time, 1000);
+        assert_eq!(points[0].count, 3);
+        assert_eq!(points[0].min, Some(200.0));
+        assert_eq!(points[0].max, Some(300.0));
+        assert_eq!(points[0].mean, Some(250.0));
+        assert_eq!(points[0].last, "250");
+        assert_eq!(points[1].time, 1180);
+        assert_eq!(points[1].mean, None);
+        assert_eq!(points[1].last, "unlimited");
+
+        let points = downsample(&samples, 1000, None);
+        assert_eq!(points.len(), 4);
+        assert_eq!(points[1].time, 1030);
+        assert_eq!(points[1].min, Some(200.0));
+    }
+}
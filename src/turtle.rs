@@ -1,15 +1,22 @@
-use crate::mining_plots::{
-    mining_orders, new_mining_position, MiningPlot, PLOT_DEPTH, PLOT_DIRECTION,
-    PLOT_MAX_DEPTH_SEGMENT,
-};
+use crate::fuel::{refuel_points, FuelBudget};
+use crate::inventory::Inventory;
+use crate::locks::TurtleLocks;
+use crate::mining_areas::{area_world, default_area};
+use crate::mining_plots::{MiningPlot, MiningTask};
+use crate::mining_strategies::StrategyName;
+use crate::order_queue::{OrderQueue, Priority};
+use crate::pathfinding::{find_path, KnownObstacles, Obstacles};
+use crate::stations::{nearest_station, Station, StationKind, StationReport};
+use crate::telemetry::{Telemetry, TelemetryTopic};
 use crate::{
-    utils::{Direction, Position},
-    MiningPlots,
+    config::{Config, WorldConfig},
+    persistance::{Storage, StorageResult},
+    utils::{unix_timestamp, Area, Direction, Position},
+    world_map::{Block, Inspection},
 };
 use log::debug;
-use mongodb::bson::{self, doc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 use strum_macros::{Display, EnumString};
 
 #[derive(Debug, Clone, Display, PartialEq, EnumString, Deserialize, Serialize)]
@@ -22,16 +29,57 @@ pub enum CommandName {
     // UpDig,
     // DownDig,
     ForwardDig,
+    /// Dig above and move forward, for tunnels two blocks high.
+    ForwardDigUp,
     Sleep,
     Reboot,
+    /// Download the latest lua script and reboot.
+    Update,
     RefuelCheck,
     DepositItem,
+    /// Take fuel from the chest in front.
+    Refuel,
+    /// Report the GPS position, and the heading when the argument is `1`.
+    Locate,
+    /// Report the block in front, above or below.
+    Inspect,
+    InspectUp,
+    InspectDown,
     // Functions
     Home,
+    /// Mine the plot of the turtle, the argument selects the strategy of its next plots (see
+    /// [`StrategyName::argument`]).
     MinePlot,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl CommandName {
+    /// Argument used when an order is given without one.
+    pub fn default_argument(&self) -> i32 {
+        match self {
+            CommandName::Up
+            | CommandName::Down
+            | CommandName::Left
+            | CommandName::Right
+            | CommandName::Forward
+            | CommandName::ForwardDig
+            | CommandName::ForwardDigUp
+            | CommandName::DepositItem
+            | CommandName::Refuel
+            | CommandName::Locate => 1,
+            CommandName::Sleep => 2,
+            CommandName::Reboot
+            | CommandName::Update
+            | CommandName::RefuelCheck
+            | CommandName::Inspect
+            | CommandName::InspectUp
+            | CommandName::InspectDown
+            | CommandName::Home
+            | CommandName::MinePlot => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Command {
     name: CommandName,
     argument: i32,
@@ -41,16 +89,179 @@ impl Command {
     pub fn new(name: CommandName, argument: i32) -> Self {
         Command { name, argument }
     }
+
+    pub fn name(&self) -> &CommandName {
+        &self.name
+    }
+
+    pub fn argument(&self) -> i32 {
+        self.argument
+    }
+}
+
+/// Lua call sent to the turtle, e.g. `Forward(3)`.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({})", self.name, self.argument)
+    }
+}
+
+/// What a turtle is given to mine.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlotOrders {
+    /// Commands mining a depth segment of a plot, or the ores of its veins.
+    Mine(MiningTask, Vec<Command>),
+    /// Every plot of the area is finished or taken.
+    NoPlotLeft,
+    /// The turtle couldn't reach a refuel point once done, nothing was planned.
+    LowFuel,
+}
+
+/// Commands sent to a turtle at once, starting with a `Batch(id)` line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Batch {
+    pub id: u32,
+    pub commands: Vec<Command>,
+    /// Number of times the batch was sent again without being acknowledged.
+    pub retries: u32,
+    /// Plot segment mined by the batch, credited with the blocks dug.
+    #[serde(default)]
+    pub mining: Option<MiningTask>,
+}
+
+impl fmt::Display for Batch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Batch({})", self.id)?;
+        for command in &self.commands {
+            write!(f, "\n{}", command)?;
+        }
+        Ok(())
+    }
+}
+
+/// Sent by the turtle after running a batch.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Ack {
+    pub batch: u32,
+    /// Number of commands fully done.
+    pub done: usize,
+    /// Steps done by the command that failed, if any.
+    #[serde(default)]
+    pub steps: i32,
+    pub fuel: Option<i32>,
+    /// Blocks dug during the batch, as `name*count` separated by commas.
+    #[serde(default)]
+    pub dug: String,
+}
+
+impl Ack {
+    /// Number of blocks dug by name, the malformed entries are skipped.
+    pub fn dug_counts(&self) -> HashMap<String, u32> {
+        let mut counts = HashMap::new();
+        for entry in self.dug.split(',').filter(|entry| !entry.is_empty()) {
+            match entry
+                .rsplit_once('*')
+                .map(|(name, count)| (name, count.parse::<u32>()))
+            {
+                Some((name, Ok(count))) => *counts.entry(name.to_string()).or_default() += count,
+                _ => log::warn!("Invalid dug blocks entry: {}", entry),
+            }
+        }
+        counts
+    }
+}
+
+/// Position reported by the turtle with `gps.locate()`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpsFix {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+    /// Found by moving forward and back, only when asked with `Locate(1)`.
+    pub heading: Option<Direction>,
+    /// Batch being run and number of its commands done, when located in the middle of a batch.
+    pub batch: Option<u32>,
+    pub done: Option<usize>,
+}
+
+impl GpsFix {
+    pub fn position(&self) -> Position {
+        Position {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+        }
+    }
+}
+
+/// Difference between the tracked position and a GPS fix.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Drift {
+    pub timestamp: u64,
+    pub expected: Position,
+    pub actual: Position,
+    pub expected_direction: Direction,
+    pub actual_direction: Option<Direction>,
+}
+
+/// Blocks around the positions known to the planners loaded from the world map.
+const MAP_MARGIN: i32 = 16;
+
+/// Number of drifts kept per turtle.
+const MAX_DRIFTS: usize = 50;
+
+/// Ore targets dug per batch.
+const MAX_VEIN_TARGETS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AckOutcome {
+    Completed,
+    /// The batch stopped at this command.
+    Failed(Command),
+    /// Not the batch the turtle is expected to run, nothing was updated.
+    UnknownBatch,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 
 pub struct Turtle {
     name: String,
-    pub orders: Vec<Command>,
+    /// Orders not planned yet, `world.idle_orders` run once it is empty.
+    #[serde(default)]
+    pub queue: OrderQueue,
+    /// Values posted on the topics that aren't telemetry, as they are.
     pub infos: HashMap<String, String>,
+    #[serde(default)]
+    pub telemetry: Telemetry,
     pub pos: Position,
     pub direction: Direction,
+    /// Commands sent to the turtle and not acknowledged yet.
+    #[serde(default)]
+    pub batch: Option<Batch>,
+    #[serde(default)]
+    last_batch_id: u32,
+    /// Latest differences found with a GPS fix, oldest first.
+    #[serde(default)]
+    pub drifts: Vec<Drift>,
+    /// Strategy of the next plots, `world.mining_strategy` when not chosen with `MinePlot`.
+    #[serde(default)]
+    pub strategy: Option<StrategyName>,
+    /// Unix timestamp of the last request or acknowledgement, turtles saved before it was recorded
+    /// count as seen when loaded.
+    #[serde(default = "unix_timestamp")]
+    pub last_seen: u64,
+    /// Not seen for `script.offline_timeout` seconds, its plot was released.
+    #[serde(default)]
+    pub offline: bool,
+    /// Mining area where the turtle gets its plots, and drops off what it dug.
+    #[serde(default = "default_area")]
+    pub area: String,
+    /// Last inventory reported by the turtle.
+    #[serde(default)]
+    pub inventory: Option<Inventory>,
+    /// Unix timestamp of the last time the samples past `telemetry.retention` were dropped.
+    #[serde(default)]
+    pub telemetry_pruned: u64,
 }
 
 #[allow(dead_code)]
@@ -59,106 +270,133 @@ const IN_WORLD_DEFAULT_POSITION: Position = Position {
     y: 63,
     z: -2615,
 };
-pub const IN_WORLD_CHEST_POSITION: Position = Position {
-    x: -559,
-    y: 63,
-    z: -2767,
-};
-
-#[allow(dead_code)]
-const IN_WORLD_SKY_POSITON: i32 = 73;
 
-#[cfg(test)]
-mod tests {
-    use crate::{
-        persistance,
-        utils::{Direction, Position},
-    };
-    use std::collections::HashMap;
-
-    use super::Turtle;
-    #[actix_web::test]
-    async fn test_go_to_order() {
-        let (_, db_mining_plots) = persistance::connect().await;
-        let pos = Position { x: 0, y: 0, z: 0 };
-
-        let mut turtle = Turtle {
-            pos,
-            direction: Direction::North,
-            name: "test".to_string(),
-            orders: Vec::new(),
-            infos: HashMap::new(),
-        };
-        let gotopos = Position { x: 4, y: 0, z: 0 };
-        let gotoorders = turtle
-            .go_to_position_orders(&gotopos, &Direction::North)
-            .unwrap();
-        println!("debug: orders= {:#?}", gotoorders);
-        turtle.orders = gotoorders;
-
-        turtle.orders(&db_mining_plots).await;
-        assert_eq!(turtle.pos, gotopos);
-        assert_eq!(turtle.direction, Direction::North);
+impl Turtle {
+    pub fn name(&self) -> &str {
+        &self.name
     }
-}
 
-impl Turtle {
-    pub fn default(name: String) -> Self {
+    pub fn default(name: String, world: &WorldConfig) -> Self {
+        let mut queue = OrderQueue::default();
+        queue.append(vec![Command::new(CommandName::Sleep, 2)], Priority::Normal);
         Turtle {
-            orders: vec![Command::new(CommandName::Sleep, 2)],
+            queue,
             infos: HashMap::new(),
-            pos: IN_WORLD_CHEST_POSITION,
+            telemetry: Telemetry::default(),
+            pos: world.chest_position,
             direction: Direction::North,
+            batch: None,
+            last_batch_id: 0,
+            drifts: Vec::new(),
+            strategy: None,
+            last_seen: unix_timestamp(),
+            offline: false,
+            area: default_area(),
+            inventory: None,
+            telemetry_pruned: 0,
             name,
         }
     }
 
+    /// Record news from the turtle at `now`, a turtle that was offline is back.
+    pub fn seen(&mut self, now: u64) {
+        if self.offline {
+            log::info!("Turtle {} is back online", self.name);
+            self.offline = false;
+        }
+        self.last_seen = now;
+    }
+
     #[allow(dead_code)]
     pub fn get_position(&self) -> String {
         format!("Position: {:#?}, Direction: {}", &self.pos, &self.direction)
     }
 
-    #[allow(dead_code)]
-    fn go_in_the_sky_orders(&mut self) -> Option<Command> {
-        if self.pos.y < IN_WORLD_SKY_POSITON {
-            Some(Command::new(
-                CommandName::Up,
-                IN_WORLD_SKY_POSITON - self.pos.y,
-            ))
-        } else {
-            None
+    fn rotate_to(&self, current: &mut Direction, wanted: Direction) -> Option<Command> {
+        let turns = current.turns_to(wanted);
+        *current = wanted;
+        match turns {
+            0 => None,
+            turns if turns > 0 => Some(Command::new(CommandName::Right, turns)),
+            turns => Some(Command::new(CommandName::Left, -turns)),
         }
     }
 
-    fn rotate_to(&self, current: &mut Direction, wanted: Direction) -> Option<Command> {
-        if wanted == *current {
-            return None;
+    /// Blocks the turtle must not dig: the chests of the home and the stations, the other turtles,
+    /// the protected areas and the avoided blocks of the world map.
+    pub async fn known_obstacles(
+        &self,
+        storage: &dyn Storage,
+        world: &WorldConfig,
+    ) -> StorageResult<KnownObstacles> {
+        let mut obstacles = KnownObstacles {
+            areas: world.protected_areas.clone(),
+            ..KnownObstacles::default()
+        };
+        for chest_side in [Direction::West, Direction::East] {
+            obstacles
+                .blocks
+                .insert(world.chest_position + chest_side.forward_vector());
         }
-        let diff_facing = wanted.clone() as i32 - current.clone() as i32;
-        *current = wanted;
-        if diff_facing > 0 {
-            Some(Command::new(CommandName::Right, diff_facing))
-        } else {
-            Some(Command::new(CommandName::Left, diff_facing.abs()))
+        for station in storage.list_stations().await? {
+            obstacles.blocks.insert(station.chest());
         }
+        for turtle in storage.list_turtles().await? {
+            if turtle.name != self.name {
+                obstacles.blocks.insert(turtle.pos);
+            }
+        }
+        // The blocks known around the turtle, its home and the mining area
+        let area = Area::new(self.pos, world.chest_position)
+            .union(&Area::new(world.mining_position, world.mining_position))
+            .grow(MAP_MARGIN + world.plots_wide * world.plot_size);
+        for block in storage.find_blocks(&area).await? {
+            if world.avoided_blocks.contains(&block.name) {
+                obstacles.blocks.insert(block.position);
+                obstacles.avoided.insert(block.position);
+            }
+        }
+        Ok(obstacles)
     }
 
+    /// Shortest path to `destination` around the obstacles, or moves along each axis, digging
+    /// through everything, when there is none.
     pub fn go_to_position_orders(
         &self,
         destination: &Position,
         destination_direction: &Direction,
+        obstacles: &dyn Obstacles,
+        world: &WorldConfig,
     ) -> Option<Vec<Command>> {
         if self.pos == *destination && self.direction == *destination_direction {
             return None;
         }
+        let path = find_path(
+            self.pos,
+            self.direction,
+            *destination,
+            Some(*destination_direction),
+            obstacles,
+            &world.path_options(),
+        );
+        Some(path.unwrap_or_else(|| {
+            log::warn!(
+                "No path for turtle {} from {:?} to {:?}, going straight",
+                self.name,
+                self.pos,
+                destination
+            );
+            self.axis_orders(destination, destination_direction)
+        }))
+    }
+
+    fn axis_orders(
+        &self,
+        destination: &Position,
+        destination_direction: &Direction,
+    ) -> Vec<Command> {
         let mut orders_to_pos = Vec::new();
-        // Don't go flying when you are near
-        // if (self.pos.x - destination.y).abs() + (self.pos.x - destination.y).abs() > (PLOT_SIZE * 2) {
-        //     if let Some(orders) = self.go_in_the_sky_orders() {
-        //         orders_to_pos.push(orders);
-        //     }
-        // }
-        let mut tmp_direction = self.direction.clone();
+        let mut tmp_direction = self.direction;
         let pos_diff = self.pos - *destination;
         if pos_diff.x > 0 {
             if let Some(orders) = self.rotate_to(&mut tmp_direction, Direction::West) {
@@ -182,161 +420,1053 @@ impl Turtle {
             CommandName::Up
         };
         orders_to_pos.push(Command::new(command, pos_diff.y.abs()));
-        if let Some(orders) = self.rotate_to(&mut tmp_direction, destination_direction.clone()) {
+        if let Some(orders) = self.rotate_to(&mut tmp_direction, *destination_direction) {
             orders_to_pos.push(orders);
         }
-        Some(orders_to_pos)
+        orders_to_pos
+    }
+
+    /// Judged from the inventory, or from the full flag when the turtle doesn't report it.
+    fn should_deposit(&self, world: &WorldConfig) -> bool {
+        match &self.inventory {
+            Some(inventory) => inventory.should_deposit(world),
+            None => self.telemetry.is_full == Some(true),
+        }
+    }
+
+    /// The value posted on a topic, telemetry or not.
+    pub fn info(&self, topic: &str) -> Option<String> {
+        match TelemetryTopic::from_name(topic) {
+            Some(topic) => self.telemetry.get(topic),
+            None => self.infos.get(topic).cloned(),
+        }
     }
 
-    fn mine_plot_orders(&self, mining_plot: MiningPlot) -> Vec<Command> {
+    /// Go to the nearest usable station of a kind and use it. Without one, go home and deposit in
+    /// the chests on each side, or just wait there for fuel.
+    fn station_orders(
+        &self,
+        kind: StationKind,
+        stations: &[Station],
+        obstacles: &dyn Obstacles,
+        world: &WorldConfig,
+    ) -> Vec<Command> {
+        if let Some(station) = nearest_station(stations, kind, self.pos) {
+            let command = match kind {
+                StationKind::Deposit => CommandName::DepositItem,
+                StationKind::Fuel => CommandName::Refuel,
+            };
+            let mut result = self
+                .go_to_position_orders(&station.position, &station.facing, obstacles, world)
+                .unwrap_or_default();
+            result.push(Command::new(command, 1));
+            return result;
+        }
+        let home = Direction::North;
+        let result = self.go_to_position_orders(&world.chest_position, &home, obstacles, world);
+        match kind {
+            StationKind::Deposit => {
+                let mut result = result.unwrap_or_default();
+                let mut direction = home;
+                for chest_side in [Direction::West, Direction::East] {
+                    result.extend(self.rotate_to(&mut direction, chest_side));
+                    result.push(Command::new(CommandName::DepositItem, 1));
+                }
+                result
+            }
+            StationKind::Fuel => {
+                result.unwrap_or_else(|| vec![Command::new(CommandName::Sleep, 2)])
+            }
+        }
+    }
+
+    fn mine_plot_orders(
+        &self,
+        mining_plot: MiningPlot,
+        obstacles: &dyn Obstacles,
+        world: &WorldConfig,
+    ) -> Vec<Command> {
         let mut result = Vec::new();
-        let mut current_plot_position_w_depth = mining_plot.position;
-        current_plot_position_w_depth.y -= (mining_plot.mined_depth_segment * PLOT_DEPTH) as i32;
+        let start = mining_plot.segment_start(mining_plot.mined_depth_segment, world);
         if let Some(mut orders) =
-            self.go_to_position_orders(&current_plot_position_w_depth, &PLOT_DIRECTION)
+            self.go_to_position_orders(&start, &world.plot_direction, obstacles, world)
         {
             result.append(&mut orders);
         }
-        result.append(&mut mining_orders());
+        result.append(&mut mining_plot.segment_orders(world));
         result
     }
 
+    /// Dig the ore targets of the plot nearest first, the targets planned are removed. The
+    /// targets blocked for now, e.g. by another turtle, or out of reach are kept for a later batch.
+    fn vein_orders(
+        &self,
+        plot: &mut MiningPlot,
+        obstacles: &dyn Obstacles,
+        world: &WorldConfig,
+    ) -> Vec<Command> {
+        plot.ore_targets
+            .retain(|target| !obstacles.is_avoided(target));
+        let mut planned = self.clone();
+        let mut result = Vec::new();
+        let mut unreachable = Vec::new();
+        for _ in 0..MAX_VEIN_TARGETS {
+            let nearest = plot
+                .ore_targets
+                .iter()
+                .enumerate()
+                .filter(|(_, target)| {
+                    !obstacles.is_blocked(target) && !unreachable.contains(*target)
+                })
+                .min_by_key(|(_, target)| {
+                    let diff = **target - planned.pos;
+                    diff.x.abs() + diff.y.abs() + diff.z.abs()
+                })
+                .map(|(index, _)| index);
+            let (index, target) = match nearest {
+                Some(index) => (index, plot.ore_targets[index]),
+                None => break,
+            };
+            // Moving into the target digs it
+            let path = find_path(
+                planned.pos,
+                planned.direction,
+                target,
+                None,
+                obstacles,
+                &world.path_options(),
+            );
+            let path = match path {
+                Some(path) => path,
+                None => {
+                    log::warn!(
+                        "No path from {:?} to the ore at {:?} of plot {}, keeping it for later",
+                        planned.pos,
+                        target,
+                        plot.id
+                    );
+                    unreachable.push(target);
+                    continue;
+                }
+            };
+            plot.ore_targets.remove(index);
+            for command in path {
+                planned.apply(&command, command.argument);
+                result.push(command);
+            }
+        }
+        result
+    }
+
+    /// Save the plot of the turtle, or release it when it can't be found anymore: the turtle would
+    /// otherwise keep planning from a plot whose progress is lost.
+    async fn save_plot(&self, storage: &dyn Storage, plot: &MiningPlot) -> StorageResult<bool> {
+        let saved = storage.save_plot(plot).await?;
+        if !saved {
+            log::error!(
+                "Mining plot {} of {} not found, releasing it",
+                plot.id,
+                self.name
+            );
+            storage.release_turtle_plot(&self.name).await?;
+        }
+        Ok(saved)
+    }
+
+    /// Orders for the ore targets of the current plot, or its next depth segment. `None` once the
+    /// plot is finished or lost.
+    async fn current_plot_orders(
+        &self,
+        mut plot: MiningPlot,
+        storage: &dyn Storage,
+        obstacles: &dyn Obstacles,
+        world: &WorldConfig,
+        budget: Option<&FuelBudget>,
+    ) -> StorageResult<Option<PlotOrders>> {
+        let fits = |orders: &[Command]| budget.is_none_or(|budget| budget.allows(self, orders));
+        if !plot.ore_targets.is_empty() {
+            let orders = self.vein_orders(&mut plot, obstacles, world);
+            if !fits(&orders) {
+                return Ok(Some(PlotOrders::LowFuel));
+            }
+            if !self.save_plot(storage, &plot).await? {
+                return Ok(None);
+            }
+            if !orders.is_empty() {
+                return Ok(Some(PlotOrders::Mine(plot.task(), orders)));
+            }
+        }
+        let next_segment = plot.mined_depth_segment + 1;
+        if next_segment <= world.max_depth_segment()
+            && !segment_is_blocked(&plot, next_segment, obstacles, world)
+        {
+            plot.mined_depth_segment += 1;
+            let task = plot.task();
+            let orders = self.mine_plot_orders(plot, obstacles, world);
+            if !fits(&orders) {
+                return Ok(Some(PlotOrders::LowFuel));
+            }
+            storage
+                .set_plot_depth_segment(&self.name, task.segment)
+                .await?;
+            Ok(Some(PlotOrders::Mine(task, orders)))
+        } else {
+            plot.finished = true;
+            plot.current_turtle = None;
+            self.save_plot(storage, &plot).await?;
+            Ok(None)
+        }
+    }
+
+    /// Orders for the plot of the turtle and the segment they mine: the ore targets first, then
+    /// the next depth segment. Once the current plot is done, a plot released by an offline
+    /// turtle is resumed where it stopped, or a new plot is created.
+    /// `world` is the config of the area of the turtle, a plot of another area is released.
+    /// Nothing is planned when the orders don't fit in the fuel `budget`.
     pub async fn resume_or_create_plot_oders(
         &mut self,
-        mining_plots: &MiningPlots,
-    ) -> Vec<Command> {
-        let current_plot = mining_plots
-            .find_one(doc! {"current_turtle": &self.name}, None)
-            .await
-            .expect("Unable to find_one from mining plots");
-        if let Some(mut current_plot) = current_plot {
-            if current_plot.mined_depth_segment < PLOT_MAX_DEPTH_SEGMENT {
-                current_plot.mined_depth_segment += 1;
-                mining_plots
-                .update_one(
-                    doc! {"current_turtle": &self.name},
-                    doc! { "$set": { "mined_depth_segment": current_plot.mined_depth_segment } },
-                    None,
-                )
-                .await
-                .expect("Unable to update mined_depth_segment in plot");
-                return self.mine_plot_orders(current_plot);
-            } else {
-                mining_plots
-                .update_one(
-                    doc! {"current_turtle": &self.name},
-                    doc! { "$set": { "current_turtle": bson::to_bson(&None::<String>).unwrap() } },
-                    None,
-                )
-                .await
-                .expect("Unable to remove clear name in plot");
+        storage: &dyn Storage,
+        obstacles: &dyn Obstacles,
+        world: &WorldConfig,
+        offline_timeout: u64,
+        budget: Option<&FuelBudget>,
+        locks: &TurtleLocks,
+    ) -> StorageResult<PlotOrders> {
+        let fits = |orders: &[Command]| budget.is_none_or(|budget| budget.allows(self, orders));
+        let mut current_plot = storage.find_turtle_plot(&self.name).await?;
+        if let Some(plot) = current_plot.take_if(|plot| plot.area != self.area) {
+            log::info!(
+                "Turtle {} moved to the {} area, releasing plot {}",
+                self.name,
+                self.area,
+                plot.id
+            );
+            storage.release_turtle_plot(&self.name).await?;
+        }
+        if let Some(current_plot) = current_plot {
+            let orders = self
+                .current_plot_orders(current_plot, storage, obstacles, world, budget)
+                .await?;
+            if let Some(orders) = orders {
+                return Ok(orders);
             }
         };
-        let document_count = mining_plots
-            .count_documents(None, None)
-            .await
-            .expect("Unable to get document count for mining_plots");
-        let new_plot_position = new_mining_position(document_count as i32);
-        let new_plot = MiningPlot::new(new_plot_position, &self.name);
-        mining_plots
-            .insert_one(&new_plot, None)
-            .await
-            .expect("Unable to add new mining plot");
-        self.mine_plot_orders(new_plot)
+        release_offline_turtles(storage, locks, offline_timeout, unix_timestamp()).await?;
+        let mut plot = storage.claim_free_plot(&self.name, &self.area).await?;
+        if let Some(plot) = &plot {
+            log::info!(
+                "Turtle {} resumes plot {} at segment {}",
+                self.name,
+                plot.id,
+                plot.mined_depth_segment
+            );
+        } else {
+            let strategy = self.strategy.unwrap_or(world.mining_strategy);
+            plot = MiningPlot::create(storage, &self.area, world, &self.name, strategy).await?;
+        }
+        let plot = match plot {
+            Some(plot) => plot,
+            None => return Ok(PlotOrders::NoPlotLeft),
+        };
+        let task = plot.task();
+        let orders = self.mine_plot_orders(plot, obstacles, world);
+        if !fits(&orders) {
+            // Left to the next turtle, resumed at the same segment
+            storage.release_turtle_plot(&self.name).await?;
+            return Ok(PlotOrders::LowFuel);
+        }
+        Ok(PlotOrders::Mine(task, orders))
     }
 
-    pub async fn orders(&mut self, mining_plots: &MiningPlots) -> String {
-        let mut result: Vec<String> = Vec::new();
+    /// Update the position and direction after `steps` steps of `command`, e.g. `2` for the
+    /// first two blocks of `Forward(3)`.
+    pub fn apply(&mut self, command: &Command, steps: i32) {
+        match command.name {
+            CommandName::Up => self.pos.y += steps,
+            CommandName::Down => self.pos.y -= steps,
+            CommandName::Forward | CommandName::ForwardDig | CommandName::ForwardDigUp => {
+                self.pos = self.pos + self.direction.forward_vector() * steps
+            }
+            CommandName::Right => self.direction = self.direction.turn_right(steps),
+            CommandName::Left => self.direction = self.direction.turn_left(steps),
+            _ => {}
+        }
+    }
 
-        let mut orders = None;
-        if let Some(fuellevel) = self.infos.get("fuellevel") {
-            let fuelvalue: i32 = fuellevel.parse().unwrap();
-            debug!("fuelvalue: {}", fuelvalue);
-            if fuelvalue < 500 {
-                println!("Turtle {} as low fuel !", self.name);
-                orders = Some(
-                    self.go_to_position_orders(&IN_WORLD_CHEST_POSITION, &Direction::North)
-                        .unwrap_or_else(|| vec![Command::new(CommandName::Sleep, 2)]),
-                );
+    /// Commit the steps reported by the turtle for its pending batch.
+    pub fn acknowledge(&mut self, ack: &Ack) -> AckOutcome {
+        let batch = match self.batch.take() {
+            Some(batch) if batch.id == ack.batch => batch,
+            batch => {
+                self.batch = batch;
+                return AckOutcome::UnknownBatch;
             }
-        } else if let Some(is_full) = self.infos.get("isFull") {
-            if is_full == "true" {
-                let mut result = self
-                    .go_to_position_orders(&IN_WORLD_CHEST_POSITION, &Direction::North)
-                    .unwrap_or_else(|| vec![Command::new(CommandName::Sleep, 2)]);
-                let mut tmp_direction = self.direction.clone();
-                if let Some(order) = self.rotate_to(&mut tmp_direction, Direction::West) {
-                    result.push(order);
-                }
-                result.push(Command::new(CommandName::DepositItem, 1));
-                if let Some(order) = self.rotate_to(&mut tmp_direction, Direction::East) {
-                    result.push(order);
-                }
-                result.push(Command::new(CommandName::DepositItem, 1));
-                orders = Some(result);
-            };
         };
-        if orders.is_none() {
-            orders = Some(self.orders.clone())
+        if let Some(fuel) = ack.fuel {
+            self.telemetry.fuel_level = Some(fuel);
+        }
+        for command in batch.commands.iter().take(ack.done) {
+            self.apply(command, command.argument);
+        }
+        match batch.commands.get(ack.done) {
+            Some(failed) => {
+                self.apply(failed, ack.steps.clamp(0, failed.argument));
+                AckOutcome::Failed(failed.clone())
+            }
+            None => AckOutcome::Completed,
+        }
+    }
+
+    /// Where the turtle is expected to be after `done` commands and `steps` steps of the next
+    /// one of the pending batch, when `batch` is its id.
+    fn during_batch(&self, batch: Option<u32>, done: Option<usize>, steps: i32) -> Turtle {
+        let mut expected = self.clone();
+        if let Some(pending) = self
+            .batch
+            .as_ref()
+            .filter(|pending| Some(pending.id) == batch)
+        {
+            let done = done.unwrap_or_default();
+            for command in pending.commands.iter().take(done) {
+                expected.apply(command, command.argument);
+            }
+            if let Some(current) = pending.commands.get(done) {
+                expected.apply(current, steps.clamp(0, current.argument));
+            }
+        }
+        expected
+    }
+
+    /// The station of the chest a turtle reported the capacity of.
+    pub fn used_station<'a>(
+        &self,
+        report: &StationReport,
+        stations: &'a [Station],
+    ) -> Option<&'a Station> {
+        let turtle = self.during_batch(report.batch, report.done, 0);
+        stations
+            .iter()
+            .find(|station| station.position == turtle.pos && station.facing == turtle.direction)
+    }
+
+    /// The block reported by an `Inspect` command.
+    pub fn inspected_block(&self, inspection: &Inspection) -> Block {
+        let turtle = self.during_batch(inspection.batch, inspection.done, inspection.steps);
+        Block {
+            position: turtle.pos + inspection.side.offset(turtle.direction),
+            name: inspection.name.clone(),
+            updated: unix_timestamp(),
+        }
+    }
+
+    /// Compare a GPS fix with the tracked position. When they disagree, the fix is kept, the
+    /// drift is recorded and the pending batch is dropped so the next orders are planned again.
+    pub fn reconcile(&mut self, fix: &GpsFix) -> Option<Drift> {
+        let expected = self.during_batch(fix.batch, fix.done, 0);
+        let actual = fix.position();
+        let direction_agrees = fix
+            .heading
+            .is_none_or(|heading| heading == expected.direction);
+        if actual == expected.pos && direction_agrees {
+            return None;
+        }
+        let drift = Drift {
+            timestamp: unix_timestamp(),
+            expected: expected.pos,
+            actual,
+            expected_direction: expected.direction,
+            actual_direction: fix.heading,
         };
+        self.pos = actual;
+        self.direction = fix.heading.unwrap_or(expected.direction);
+        self.batch = None;
+        self.drifts.push(drift.clone());
+        if self.drifts.len() > MAX_DRIFTS {
+            self.drifts.remove(0);
+        }
+        Some(drift)
+    }
 
-        for Command { name, argument } in orders.unwrap() {
-            let sub_orders: Option<Vec<_>> = match name {
-                CommandName::Home => {
-                    self.go_to_position_orders(&IN_WORLD_CHEST_POSITION, &Direction::North)
+    /// Orders to send to the turtle, as a new batch to acknowledge. A batch that was not
+    /// acknowledged is sent again up to `script.max_batch_retries` times, then dropped.
+    pub async fn orders(
+        &mut self,
+        storage: &dyn Storage,
+        config: &Config,
+        locks: &TurtleLocks,
+    ) -> StorageResult<String> {
+        if let Some(batch) = &mut self.batch {
+            if batch.retries < config.script.max_batch_retries {
+                batch.retries += 1;
+                log::warn!(
+                    "Turtle {} didn't acknowledge batch {}, sending it again",
+                    self.name,
+                    batch.id
+                );
+                return Ok(batch.to_string());
+            }
+            log::error!(
+                "Turtle {} didn't acknowledge batch {}, its position may be wrong",
+                self.name,
+                batch.id
+            );
+            self.telemetry.last_error = Some(format!("batch {} was not acknowledged", batch.id));
+            self.batch = None;
+        }
+
+        // Planned in the area of the turtle, going home is going to its drop-off chest
+        let world = &area_world(storage, &config.world, &self.area).await?;
+        let obstacles = self.known_obstacles(storage, world).await?;
+        let stations = storage.list_stations().await?;
+        let fuel_level = self.telemetry.fuel_level;
+        debug!("fuel level: {:?}", fuel_level);
+        let mut orders = None;
+        if fuel_level.is_some_and(|fuel_level| fuel_level < world.min_fuel_level) {
+            log::warn!("Turtle {} has low fuel", self.name);
+            orders = Some(self.station_orders(StationKind::Fuel, &stations, &obstacles, world));
+        } else if self.should_deposit(world) {
+            log::info!("Turtle {} goes to deposit", self.name);
+            orders = Some(self.station_orders(StationKind::Deposit, &stations, &obstacles, world));
+        };
+        // Work is only planned when the turtle can reach a refuel point once done
+        let mut budget = None;
+        // With the id of the queued ones, removed from the queue once planned
+        let orders: Vec<(Option<u32>, Command)> = match orders {
+            Some(orders) => orders.into_iter().map(|command| (None, command)).collect(),
+            None => {
+                budget = fuel_level.map(|level| FuelBudget {
+                    level,
+                    reserve: world.fuel_reserve,
+                    refuel_points: refuel_points(&stations, world),
+                });
+                if self.queue.is_empty() {
+                    let idle = world.idle_orders();
+                    idle.into_iter().map(|command| (None, command)).collect()
+                } else {
+                    let queue = self.queue.orders().iter();
+                    queue
+                        .map(|order| (Some(order.id), order.command.clone()))
+                        .collect()
                 }
-                CommandName::MinePlot => Some(self.resume_or_create_plot_oders(mining_plots).await),
-                _ => Some(vec![Command { name, argument }]),
-            };
-            if let Some(sub_orders) = sub_orders {
-                for Command { name, argument } in sub_orders {
-                    let argument = argument as i32;
-                    let order_str = match name {
-                        CommandName::Up => {
-                            self.pos.y += argument;
-                            format!("{}({})", name, argument)
-                        }
-                        CommandName::Down => {
-                            self.pos.y -= argument;
-                            format!("{}({})", name, argument)
-                        }
-                        CommandName::Forward | CommandName::ForwardDig => {
-                            match self.direction {
-                                Direction::North => self.pos.z -= argument,
-                                Direction::East => self.pos.x += argument,
-                                Direction::South => self.pos.z += argument,
-                                Direction::West => self.pos.x -= argument,
-                            };
-                            format!("{}({})", name, argument)
-                        }
-                        CommandName::Right => {
-                            let u8facing: usize = self.direction.clone() as usize;
-                            self.direction =
-                                Direction::from_repr((u8facing + argument as usize) % 4).unwrap();
-                            format!("{}({})", name, argument)
-                        }
-                        CommandName::Left => {
-                            let u8facing: usize = self.direction.clone() as usize;
-                            let newfacing = if u8facing == 0 {
-                                3
-                            } else {
-                                (u8facing - argument as usize) % 4
-                            };
-                            self.direction = Direction::from_repr(newfacing).unwrap();
-                            format!("{}({})", name, argument)
+            }
+        };
+
+        // Plan from where the turtle will be, the position is only updated when acknowledged
+        let mut planned = self.clone();
+        let mut commands = Vec::new();
+        let mut mining = None;
+        for (id, command) in orders {
+            let (name, argument) = (command.name.clone(), command.argument);
+            let mut low_fuel = false;
+            let mut sub_orders: Option<Vec<_>> = match command.name {
+                CommandName::Home => planned.go_to_position_orders(
+                    &world.chest_position,
+                    &Direction::North,
+                    &obstacles,
+                    world,
+                ),
+                CommandName::MinePlot => {
+                    if let Some(strategy) = StrategyName::from_argument(command.argument) {
+                        self.strategy = Some(strategy);
+                        planned.strategy = Some(strategy);
+                    }
+                    match planned
+                        .resume_or_create_plot_oders(
+                            storage,
+                            &obstacles,
+                            world,
+                            config.script.offline_timeout,
+                            budget.as_ref(),
+                            locks,
+                        )
+                        .await?
+                    {
+                        PlotOrders::Mine(task, orders) => {
+                            mining = Some(task);
+                            Some(orders)
                         }
-                        CommandName::Reboot | CommandName::Sleep | CommandName::RefuelCheck => {
-                            format!("{}({})", name, argument)
+                        PlotOrders::NoPlotLeft => {
+                            log::warn!("No mining plot left for {}, sending it home", self.name);
+                            planned.go_to_position_orders(
+                                &world.chest_position,
+                                &Direction::North,
+                                &obstacles,
+                                world,
+                            )
                         }
-                        _ => {
-                            panic!("Order process missing: {}", name)
+                        PlotOrders::LowFuel => {
+                            low_fuel = true;
+                            None
                         }
-                    };
-                    result.push(order_str);
+                    }
+                }
+                _ => Some(vec![command]),
+            };
+            if let Some((budget, orders)) = budget.as_mut().zip(sub_orders.as_mut()) {
+                if !budget.allows(&planned, orders) {
+                    *orders = budget.truncate(&planned, orders);
+                    low_fuel = true;
+                }
+                budget.spend(orders);
+            }
+            // Cut short by the fuel, the rest of a move stays queued
+            if let Some(id) = id {
+                let done: i32 = sub_orders.iter().flatten().map(|c| c.argument).sum();
+                match name {
+                    _ if !low_fuel => {
+                        self.queue.cancel(id);
+                    }
+                    CommandName::Home | CommandName::MinePlot => {}
+                    name => self.queue.update(id, Command::new(name, argument - done)),
                 }
             }
+            for command in sub_orders.into_iter().flatten() {
+                planned.apply(&command, command.argument);
+                commands.push(command);
+            }
+            if low_fuel {
+                log::warn!(
+                    "Turtle {} would run out of fuel, sending it to refuel",
+                    self.name
+                );
+                let orders =
+                    planned.station_orders(StationKind::Fuel, &stations, &obstacles, world);
+                commands.extend(orders);
+                break;
+            }
+        }
+        self.last_batch_id += 1;
+        let batch = Batch {
+            id: self.last_batch_id,
+            commands,
+            retries: 0,
+            mining,
+        };
+        let result = batch.to_string();
+        self.batch = Some(batch);
+        Ok(result)
+    }
+}
+
+/// Mark the turtles not seen for `timeout` seconds offline and release their plots, resumed by
+/// the next turtles looking for a plot.
+pub async fn release_offline_turtles(
+    storage: &dyn Storage,
+    locks: &TurtleLocks,
+    timeout: u64,
+    now: u64,
+) -> StorageResult<()> {
+    let gone = |turtle: &Turtle| !turtle.offline && now.saturating_sub(turtle.last_seen) > timeout;
+    for turtle in storage.list_turtles().await? {
+        if !gone(&turtle) {
+            continue;
+        }
+        // A turtle in the middle of a request is online. Waiting for its lock while holding the
+        // lock of the caller could deadlock with a request doing the same the other way.
+        let lock = locks.turtle(&turtle.name);
+        let _guard = match lock.try_lock() {
+            Some(guard) => guard,
+            None => continue,
+        };
+        let turtle = match storage.find_turtle(&turtle.name).await? {
+            Some(turtle) if gone(&turtle) => turtle,
+            _ => continue,
+        };
+        log::warn!(
+            "Turtle {} was not seen for {} seconds, releasing its plot",
+            turtle.name,
+            now.saturating_sub(turtle.last_seen)
+        );
+        storage.set_turtle_offline(&turtle.name, true).await?;
+        storage.release_turtle_plot(&turtle.name).await?;
+    }
+    Ok(())
+}
+
+/// Whether a depth segment of a plot holds a block that must never be dug, e.g. bedrock. Another
+/// turtle in the way doesn't count, it moves on.
+fn segment_is_blocked(
+    plot: &MiningPlot,
+    segment: u32,
+    obstacles: &dyn Obstacles,
+    world: &WorldConfig,
+) -> bool {
+    plot.segment_positions(segment, world)
+        .iter()
+        .any(|position| obstacles.is_avoided(position))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::{Config, WorldConfig},
+        locks::TurtleLocks,
+        mining_areas::DEFAULT_AREA,
+        mining_plots::MiningPlot,
+        mining_strategies::StrategyName,
+        order_queue::{OrderQueue, Priority},
+        pathfinding::KnownObstacles,
+        persistance::{MemoryStorage, Storage},
+        telemetry::Telemetry,
+        utils::{Direction, Position},
+        world_map::{Inspection, Side},
+    };
+    use std::collections::HashMap;
+
+    use super::{
+        release_offline_turtles, unix_timestamp, Ack, AckOutcome, Command, CommandName, GpsFix,
+        Turtle,
+    };
+
+    /// Acknowledge every command of the pending batch.
+    fn acknowledge_batch(turtle: &mut Turtle) {
+        let batch = turtle.batch.clone().unwrap();
+        let ack = Ack {
+            batch: batch.id,
+            done: batch.commands.len(),
+            steps: 0,
+            fuel: None,
+            dug: String::new(),
+        };
+        assert_eq!(turtle.acknowledge(&ack), AckOutcome::Completed);
+        assert!(turtle.batch.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_go_to_order() {
+        let storage = MemoryStorage::default();
+        let pos = Position { x: 0, y: 0, z: 0 };
+
+        let mut turtle = Turtle {
+            pos,
+            direction: Direction::North,
+            name: "test".to_string(),
+            queue: OrderQueue::default(),
+            infos: HashMap::new(),
+            telemetry: Telemetry::default(),
+            batch: None,
+            last_batch_id: 0,
+            drifts: Vec::new(),
+            strategy: None,
+            last_seen: 0,
+            offline: false,
+            area: DEFAULT_AREA.to_string(),
+            inventory: None,
+            telemetry_pruned: 0,
+        };
+        let gotopos = Position { x: 4, y: 0, z: 0 };
+        let gotoorders = turtle
+            .go_to_position_orders(
+                &gotopos,
+                &Direction::North,
+                &KnownObstacles::default(),
+                &WorldConfig::default(),
+            )
+            .unwrap();
+        println!("debug: orders= {:#?}", gotoorders);
+        turtle.queue.replace(gotoorders, Priority::Normal);
+
+        turtle
+            .orders(&storage, &Config::default(), &TurtleLocks::default())
+            .await
+            .unwrap();
+        assert_eq!(turtle.pos, pos);
+        acknowledge_batch(&mut turtle);
+        assert_eq!(turtle.pos, gotopos);
+        assert_eq!(turtle.direction, Direction::North);
+    }
+
+    #[actix_web::test]
+    async fn test_rotation_tracking() {
+        let storage = MemoryStorage::default();
+        let config = Config::default();
+        let world = &config.world;
+        let mut turtle = Turtle::default("test".to_string(), world);
+        let mut direction = Direction::North;
+        assert_eq!(
+            turtle.rotate_to(&mut direction, Direction::West),
+            Some(Command::new(CommandName::Left, 1))
+        );
+        assert_eq!(direction, Direction::West);
+
+        turtle.queue.replace(
+            vec![
+                Command::new(CommandName::Left, 5),
+                Command::new(CommandName::Forward, 2),
+                Command::new(CommandName::Left, 6),
+            ],
+            Priority::Normal,
+        );
+        turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        acknowledge_batch(&mut turtle);
+        assert_eq!(turtle.direction, Direction::East);
+        assert_eq!(
+            turtle.pos,
+            world.chest_position + Position { x: -2, y: 0, z: 0 }
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_partial_acknowledgement() {
+        let storage = MemoryStorage::default();
+        let config = Config::default();
+        let world = &config.world;
+        let mut turtle = Turtle::default("test".to_string(), world);
+        turtle.queue.replace(
+            vec![
+                Command::new(CommandName::Right, 1),
+                Command::new(CommandName::Forward, 3),
+                Command::new(CommandName::Up, 2),
+            ],
+            Priority::Normal,
+        );
+        let orders = turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        assert_eq!(orders, "Batch(1)\nRight(1)\nForward(3)\nUp(2)");
+
+        let ack = Ack {
+            batch: 2,
+            done: 3,
+            steps: 0,
+            fuel: None,
+            dug: String::new(),
+        };
+        assert_eq!(turtle.acknowledge(&ack), AckOutcome::UnknownBatch);
+        assert!(turtle.batch.is_some());
+
+        // Blocked after two blocks
+        let ack = Ack {
+            batch: 1,
+            done: 1,
+            steps: 2,
+            fuel: Some(120),
+            dug: "minecraft:stone*2,invalid,minecraft:iron_ore*1".to_string(),
+        };
+        assert_eq!(
+            ack.dug_counts(),
+            HashMap::from([
+                ("minecraft:stone".to_string(), 2),
+                ("minecraft:iron_ore".to_string(), 1)
+            ])
+        );
+        assert_eq!(
+            turtle.acknowledge(&ack),
+            AckOutcome::Failed(Command::new(CommandName::Forward, 3))
+        );
+        assert!(turtle.batch.is_none());
+        assert_eq!(turtle.direction, Direction::East);
+        assert_eq!(
+            turtle.pos,
+            world.chest_position + Position { x: 2, y: 0, z: 0 }
+        );
+        assert_eq!(turtle.telemetry.fuel_level, Some(120));
+    }
+
+    #[actix_web::test]
+    async fn test_unacknowledged_batch() {
+        let storage = MemoryStorage::default();
+        let mut config = Config::default();
+        config.script.max_batch_retries = 1;
+        let mut turtle = Turtle::default("test".to_string(), &config.world);
+        turtle.queue.replace(
+            vec![Command::new(CommandName::Forward, 3)],
+            Priority::Normal,
+        );
+        let orders = turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            turtle
+                .orders(&storage, &config, &TurtleLocks::default())
+                .await
+                .unwrap(),
+            orders
+        );
+        assert_eq!(turtle.telemetry.last_error, None);
+
+        turtle
+            .queue
+            .replace(vec![Command::new(CommandName::Up, 1)], Priority::Normal);
+        let orders = turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        assert_eq!(orders, "Batch(2)\nUp(1)");
+        assert_eq!(
+            turtle.telemetry.last_error.as_deref(),
+            Some("batch 1 was not acknowledged")
+        );
+        assert_eq!(turtle.pos, config.world.chest_position);
+    }
+
+    #[actix_web::test]
+    async fn test_reconcile() {
+        let storage = MemoryStorage::default();
+        let config = Config::default();
+        let home = config.world.chest_position;
+        let mut turtle = Turtle::default("test".to_string(), &config.world);
+        turtle.queue.replace(
+            vec![
+                Command::new(CommandName::Forward, 2),
+                Command::new(CommandName::Locate, 1),
+                Command::new(CommandName::Up, 1),
+            ],
+            Priority::Normal,
+        );
+        turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        let mut fix = GpsFix {
+            x: home.x,
+            y: home.y,
+            z: home.z - 2,
+            heading: Some(Direction::North),
+            batch: Some(1),
+            done: Some(1),
+        };
+        assert_eq!(turtle.reconcile(&fix), None);
+        assert!(turtle.batch.is_some());
+
+        // Only moved one block
+        fix.z += 1;
+        let drift = turtle.reconcile(&fix).unwrap();
+        assert_eq!(drift.expected, home + Position { x: 0, y: 0, z: -2 });
+        assert_eq!(drift.actual, fix.position());
+        assert_eq!(turtle.pos, fix.position());
+        assert!(turtle.batch.is_none());
+        assert_eq!(turtle.drifts, vec![drift]);
+
+        fix.heading = Some(Direction::South);
+        fix.batch = None;
+        assert!(turtle.reconcile(&fix).is_some());
+        assert_eq!(turtle.direction, Direction::South);
+        assert_eq!(turtle.drifts.len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn test_blocked_segment() {
+        let storage = MemoryStorage::default();
+        let config = Config::default();
+        let mut turtle = Turtle::default("test".to_string(), &config.world);
+        turtle.queue.replace(
+            vec![Command::new(CommandName::MinePlot, 1)],
+            Priority::Normal,
+        );
+        turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        acknowledge_batch(&mut turtle);
+
+        let plot = storage.find_turtle_plot("test").await.unwrap().unwrap();
+        let inspection = Inspection {
+            side: Side::Down,
+            name: "minecraft:bedrock".to_string(),
+            batch: None,
+            done: None,
+            steps: 0,
+        };
+        // Bedrock in the next segment, found from above the plot
+        turtle.pos = plot.position + Position { x: 2, y: -3, z: -1 };
+        let block = turtle.inspected_block(&inspection);
+        assert_eq!(
+            block.position,
+            plot.position + Position { x: 2, y: -4, z: -1 }
+        );
+        storage.save_blocks(&[block]).await.unwrap();
+
+        turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        let plots = storage.list_plots().await.unwrap();
+        assert_eq!(plots.len(), 2);
+        assert_eq!(plots[0].mined_depth_segment, 0);
+        assert_eq!(plots[0].current_turtle, None);
+        assert!(plots[0].finished);
+        assert_eq!(plots[1].current_turtle.as_deref(), Some("test"));
+    }
+
+    #[actix_web::test]
+    async fn test_turtle_in_next_segment() {
+        let storage = MemoryStorage::default();
+        let config = Config::default();
+        let mut turtle = Turtle::default("test".to_string(), &config.world);
+        turtle.queue.replace(
+            vec![Command::new(CommandName::MinePlot, 1)],
+            Priority::Normal,
+        );
+        turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        acknowledge_batch(&mut turtle);
+
+        // Passing by in the next segment, the plot goes on
+        let plot = storage.find_turtle_plot("test").await.unwrap().unwrap();
+        let mut other = Turtle::default("other".to_string(), &config.world);
+        other.pos = plot.position + Position { x: 2, y: -4, z: -1 };
+        storage.insert_turtle(&other).await.unwrap();
+
+        turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        let plots = storage.list_plots().await.unwrap();
+        assert_eq!(plots.len(), 1);
+        assert_eq!(plots[0].mined_depth_segment, 1);
+        assert!(!plots[0].finished);
+        assert_eq!(plots[0].current_turtle.as_deref(), Some("test"));
+    }
+
+    #[actix_web::test]
+    async fn test_no_plot_left() {
+        let storage = MemoryStorage::default();
+        let mut config = Config::default();
+        config.world.max_plots = Some(1);
+        let mut first = Turtle::default("first".to_string(), &config.world);
+        first.queue.replace(
+            vec![Command::new(CommandName::MinePlot, 0)],
+            Priority::Normal,
+        );
+        first
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        storage.insert_turtle(&first).await.unwrap();
+
+        let mut second = Turtle::default("second".to_string(), &config.world);
+        second.pos = config.world.chest_position + Position { x: 2, y: 0, z: 0 };
+        second.queue.replace(
+            vec![Command::new(CommandName::MinePlot, 0)],
+            Priority::Normal,
+        );
+        second
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        assert_eq!(second.batch.as_ref().unwrap().mining, None);
+        acknowledge_batch(&mut second);
+        assert_eq!(second.pos, config.world.chest_position);
+        assert_eq!(storage.list_plots().await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn test_queue_kept_on_low_fuel() {
+        let storage = MemoryStorage::default();
+        let mut config = Config::default();
+        config.world.min_fuel_level = 0;
+        config.world.fuel_reserve = 0;
+        let mut turtle = Turtle::default("test".to_string(), &config.world);
+        turtle.telemetry.fuel_level = Some(6);
+        turtle.queue.replace(
+            vec![
+                Command::new(CommandName::Forward, 10),
+                Command::new(CommandName::Up, 1),
+            ],
+            Priority::Normal,
+        );
+        let orders = turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        assert!(orders.starts_with("Batch(1)\nForward(3)\n"), "{}", orders);
+        let queued: Vec<_> = turtle.queue.commands().cloned().collect();
+        assert_eq!(
+            queued,
+            [
+                Command::new(CommandName::Forward, 7),
+                Command::new(CommandName::Up, 1),
+            ]
+        );
+
+        // Runs the idle orders once the queue is empty
+        acknowledge_batch(&mut turtle);
+        turtle.telemetry.fuel_level = Some(1000);
+        turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        acknowledge_batch(&mut turtle);
+        assert!(turtle.queue.is_empty());
+        config.world.idle_orders = "Sleep,30".to_string();
+        let orders = turtle
+            .orders(&storage, &config, &TurtleLocks::default())
+            .await
+            .unwrap();
+        assert_eq!(orders, "Batch(3)\nSleep(30)");
+    }
+
+    #[actix_web::test]
+    async fn test_release_offline_turtles() {
+        let storage = MemoryStorage::default();
+        let locks = TurtleLocks::default();
+        let world = WorldConfig::default();
+        let now = unix_timestamp();
+        for name in ["lost", "busy"] {
+            let mut turtle = Turtle::default(name.to_string(), &world);
+            turtle.last_seen = now - 100;
+            storage.insert_turtle(&turtle).await.unwrap();
+        }
+        // Saved before the turtles were timed
+        let mut legacy = Turtle::default("legacy".to_string(), &world);
+        legacy.last_seen = 0;
+        let mut legacy = serde_json::to_value(legacy).unwrap();
+        assert!(legacy
+            .as_object_mut()
+            .unwrap()
+            .remove("last_seen")
+            .is_some());
+        let legacy: Turtle = serde_json::from_value(legacy).unwrap();
+        assert!(legacy.last_seen >= now);
+        storage.insert_turtle(&legacy).await.unwrap();
+
+        let busy = locks.turtle("busy");
+        let _guard = busy.lock().await;
+        release_offline_turtles(&storage, &locks, 60, now)
+            .await
+            .unwrap();
+        let offline: Vec<_> = storage
+            .list_turtles()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|turtle| turtle.offline)
+            .map(|turtle| turtle.name)
+            .collect();
+        assert_eq!(offline, ["lost"]);
+    }
+
+    #[test]
+    fn test_unreachable_ore_kept() {
+        let world = WorldConfig::default();
+        let mut turtle = Turtle::default("test".to_string(), &world);
+        turtle.pos = Position { x: 0, y: 0, z: 0 };
+        let mut plot =
+            MiningPlot::new(0, DEFAULT_AREA, 0, turtle.pos, "test", StrategyName::Quarry);
+        let near = Position { x: 0, y: 0, z: -2 };
+        let walled = Position { x: 5, y: 0, z: 0 };
+        plot.ore_targets = vec![walled, near];
+        let mut obstacles = KnownObstacles::default();
+        for side in [
+            Direction::North.forward_vector(),
+            Direction::East.forward_vector(),
+            Direction::South.forward_vector(),
+            Direction::West.forward_vector(),
+            Position { x: 0, y: 1, z: 0 },
+            Position { x: 0, y: -1, z: 0 },
+        ] {
+            obstacles.blocks.insert(walled + side);
         }
-        self.orders = vec![Command::new(CommandName::MinePlot, 1)];
-        result.join("\n")
+        let orders = turtle.vein_orders(&mut plot, &obstacles, &world);
+        assert_eq!(orders, [Command::new(CommandName::Forward, 2)]);
+        assert_eq!(plot.ore_targets, [walled]);
     }
 }
@@ -1,14 +1,109 @@
 use serde::{Deserialize, Serialize};
-use std::ops::{Add, Sub};
-use strum_macros::{Display, FromRepr};
+use std::{
+    ops::{Add, Mul, Sub},
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use strum_macros::{Display, EnumString, FromRepr};
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// Seconds since the unix epoch.
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
     pub z: i32,
 }
 
+/// Box between two corners, both included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Area {
+    pub from: Position,
+    pub to: Position,
+}
+
+impl Area {
+    pub fn new(from: Position, to: Position) -> Self {
+        Area { from, to }
+    }
+
+    pub fn min(&self) -> Position {
+        Position {
+            x: self.from.x.min(self.to.x),
+            y: self.from.y.min(self.to.y),
+            z: self.from.z.min(self.to.z),
+        }
+    }
+
+    pub fn max(&self) -> Position {
+        Position {
+            x: self.from.x.max(self.to.x),
+            y: self.from.y.max(self.to.y),
+            z: self.from.z.max(self.to.z),
+        }
+    }
+
+    pub fn contains(&self, position: &Position) -> bool {
+        let (min, max) = (self.min(), self.max());
+        (min.x..=max.x).contains(&position.x)
+            && (min.y..=max.y).contains(&position.y)
+            && (min.z..=max.z).contains(&position.z)
+    }
+
+    /// Whether the two areas share a position.
+    pub fn intersects(&self, other: &Area) -> bool {
+        let (min, max) = (self.min(), self.max());
+        let (other_min, other_max) = (other.min(), other.max());
+        min.x <= other_max.x
+            && other_min.x <= max.x
+            && min.y <= other_max.y
+            && other_min.y <= max.y
+            && min.z <= other_max.z
+            && other_min.z <= max.z
+    }
+
+    /// Smallest area holding both areas.
+    pub fn union(&self, other: &Area) -> Self {
+        Area::new(
+            Area::new(self.min(), other.min()).min(),
+            Area::new(self.max(), other.max()).max(),
+        )
+    }
+
+    /// The area with `margin` more blocks on every side.
+    pub fn grow(&self, margin: i32) -> Self {
+        let margin = Position {
+            x: margin,
+            y: margin,
+            z: margin,
+        };
+        Area::new(self.min() - margin, self.max() + margin)
+    }
+}
+
+/// Parse `x,y,z`, as used for positions given through environment variables.
+impl FromStr for Position {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coordinates = s
+            .split(',')
+            .map(|coordinate| coordinate.trim().parse::<i32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| format!("invalid position `{}`: {}", s, error))?;
+        match coordinates[..] {
+            [x, y, z] => Ok(Position { x, y, z }),
+            _ => Err(format!("invalid position `{}`, expected `x,y,z`", s)),
+        }
+    }
+}
+
 impl Sub for Position {
     type Output = Self;
 
@@ -21,6 +116,18 @@ impl Sub for Position {
     }
 }
 
+impl Mul<i32> for Position {
+    type Output = Self;
+
+    fn mul(self, factor: i32) -> Self::Output {
+        Self {
+            x: self.x * factor,
+            y: self.y * factor,
+            z: self.z * factor,
+        }
+    }
+}
+
 impl Add for Position {
     type Output = Self;
 
@@ -33,10 +140,96 @@ impl Add for Position {
     }
 }
 
-#[derive(Debug, Display, FromRepr, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(
+    Debug, Display, EnumString, FromRepr, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
 pub enum Direction {
     North = 0,
     East = 1,
     South = 2,
     West = 3,
 }
+
+impl Direction {
+    /// Direction after turning right `times` quarter turns, negative values turn left.
+    pub fn turn_right(self, times: i32) -> Self {
+        Direction::from_repr((self as i32 + times).rem_euclid(4) as usize).unwrap()
+    }
+
+    /// Direction after turning left `times` quarter turns, negative values turn right.
+    pub fn turn_left(self, times: i32) -> Self {
+        self.turn_right(-times)
+    }
+
+    /// Quarter turns to face `wanted` the shortest way, positive to the right and negative to
+    /// the left. A half turn is done to the right.
+    pub fn turns_to(self, wanted: Direction) -> i32 {
+        match (wanted as i32 - self as i32).rem_euclid(4) {
+            3 => -1,
+            turns => turns,
+        }
+    }
+
+    /// Offset of the block in front, `z` goes down to the north.
+    pub fn forward_vector(self) -> Position {
+        match self {
+            Direction::North => Position { x: 0, y: 0, z: -1 },
+            Direction::East => Position { x: 1, y: 0, z: 0 },
+            Direction::South => Position { x: 0, y: 0, z: 1 },
+            Direction::West => Position { x: -1, y: 0, z: 0 },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Direction, Position};
+    use proptest::prelude::*;
+
+    fn direction() -> impl Strategy<Value = Direction> {
+        (0..4usize).prop_map(|repr| Direction::from_repr(repr).unwrap())
+    }
+
+    #[test]
+    fn test_turns() {
+        assert_eq!(Direction::North.turn_left(1), Direction::West);
+        assert_eq!(Direction::East.turn_left(3), Direction::South);
+        assert_eq!(Direction::West.turn_right(1), Direction::North);
+        assert_eq!(Direction::North.turns_to(Direction::West), -1);
+        assert_eq!(Direction::North.turns_to(Direction::South), 2);
+        assert_eq!(Direction::West.turns_to(Direction::North), 1);
+        assert_eq!(
+            Direction::East.forward_vector() * 3,
+            Position { x: 3, y: 0, z: 0 }
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_turns_compose(start in direction(), a in -100..100i32, b in -100..100i32) {
+            prop_assert_eq!(start.turn_right(a).turn_right(b), start.turn_right(a + b));
+            prop_assert_eq!(start.turn_left(a).turn_left(b), start.turn_left(a + b));
+            prop_assert_eq!(start.turn_right(a).turn_left(a), start);
+            prop_assert_eq!(start.turn_right(4 * a), start);
+        }
+
+        #[test]
+        fn test_turns_to(start in direction(), wanted in direction()) {
+            let turns = start.turns_to(wanted);
+            prop_assert!((-1..=2).contains(&turns));
+            prop_assert_eq!(start.turn_right(turns), wanted);
+        }
+
+        #[test]
+        fn test_forward_vector(start in direction(), turns in 0..4i32) {
+            let direction = start.turn_right(turns);
+            prop_assert_eq!(
+                direction.forward_vector() + direction.turn_right(2).forward_vector(),
+                Position { x: 0, y: 0, z: 0 }
+            );
+            let side = direction.turn_right(1).forward_vector();
+            let forward = direction.forward_vector();
+            prop_assert_eq!(forward.x * side.x + forward.z * side.z, 0);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Blocks of the Minecraft world, as reported by the turtles with the `Inspect` commands.
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{Area, Direction, Position};
+
+/// Name reported when there is no block.
+pub const AIR: &str = "minecraft:air";
+/// Largest number of positions in a region queried through the API.
+pub const MAX_REGION_VOLUME: i64 = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Block {
+    pub position: Position,
+    pub name: String,
+    /// When the block was reported, in seconds since the unix epoch.
+    pub updated: u64,
+}
+
+/// Side of the turtle a block was inspected on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Front,
+    Up,
+    Down,
+}
+
+impl Side {
+    /// Offset of the block on this side of a turtle facing `direction`.
+    pub fn offset(self, direction: Direction) -> Position {
+        match self {
+            Side::Front => direction.forward_vector(),
+            Side::Up => Position { x: 0, y: 1, z: 0 },
+            Side::Down => Position { x: 0, y: -1, z: 0 },
+        }
+    }
+}
+
+/// Sent by the `Inspect`, `InspectUp` and `InspectDown` commands.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Inspection {
+    pub side: Side,
+    pub name: String,
+    /// Batch being run and number of its commands done, when inspected in the middle of a batch.
+    pub batch: Option<u32>,
+    pub done: Option<usize>,
+    /// Steps done by the command running, for the blocks dug while moving.
+    #[serde(default)]
+    pub steps: i32,
+}
+
+/// Number of positions in an area.
+pub fn volume(area: &Area) -> i64 {
+    let size = area.max() - area.min();
+    (size.x as i64 + 1) * (size.y as i64 + 1) * (size.z as i64 + 1)
+}
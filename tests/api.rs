@@ -0,0 +1,310 @@
+use actix_http::Request;
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    test, Error,
+};
+use cc_api::{
+    config::Config,
+    persistance::{MemoryStorage, Storage},
+    turtle::Turtle,
+};
+use serde_json::json;
+use std::{fmt::Debug, sync::Arc};
+
+mod common;
+
+use common::{call, create_plot, test_config};
+
+/// Storage holding a turtle named `miner` and a plot it mines.
+async fn test_storage(config: &Config) -> Arc<dyn Storage> {
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    storage
+        .insert_turtle(&Turtle::default("miner".to_string(), &config.world))
+        .await
+        .unwrap();
+    create_plot(storage.as_ref(), config, "miner").await;
+    storage
+}
+
+/// Send each request and check the status of its response.
+async fn expect<S, B>(app: &S, requests: Vec<(Request, u16)>)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+    B::Error: Debug,
+{
+    for (request, expected) in requests {
+        let uri = request.uri().to_string();
+        let (status, body) = call(app, request).await;
+        assert_eq!(status.as_u16(), expected, "{}: {}", uri, body);
+        if expected != 200 {
+            let error: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert!(error["error"].is_string(), "{}: {}", uri, body);
+        }
+    }
+}
+
+fn get(uri: &str) -> Request {
+    test::TestRequest::get().uri(uri).to_request()
+}
+
+fn put(uri: &str, body: serde_json::Value) -> Request {
+    test::TestRequest::put()
+        .uri(uri)
+        .set_json(&body)
+        .to_request()
+}
+
+fn post(uri: &str, body: serde_json::Value) -> Request {
+    test::TestRequest::post()
+        .uri(uri)
+        .set_json(&body)
+        .to_request()
+}
+
+fn delete(uri: &str) -> Request {
+    test::TestRequest::delete().uri(uri).to_request()
+}
+
+#[actix_web::test]
+async fn test_turtles() {
+    let config = test_config();
+    let app = init_app!(test_storage(&config).await, config.clone());
+    expect(
+        &app,
+        vec![
+            (get("/api/v1/turtles"), 200),
+            (get("/api/v1/turtles/miner"), 200),
+            (get("/api/v1/turtles/nobody"), 404),
+            (get("/api/v1/turtles/miner/infos"), 200),
+            (get("/api/v1/turtles/miner/infos/mood"), 404),
+            (get("/api/v1/turtles/miner/inventory"), 404),
+            (get("/api/v1/turtles/miner/fuel"), 200),
+            (get("/api/v1/turtles/nobody/fuel"), 404),
+            (get("/api/v1/turtles/miner/drifts"), 200),
+            (get("/api/v1/turtles/nobody/drifts"), 404),
+            (
+                put("/api/v1/turtles/miner/area", json!({ "area": "default" })),
+                200,
+            ),
+            (
+                put("/api/v1/turtles/nobody/area", json!({ "area": "default" })),
+                404,
+            ),
+            (
+                put("/api/v1/turtles/miner/area", json!({ "zone": "default" })),
+                422,
+            ),
+        ],
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_turtle_orders() {
+    let config = test_config();
+    let app = init_app!(test_storage(&config).await, config.clone());
+    let commands = json!({ "orders": [{ "name": "Forward", "argument": 2 }] });
+    let negative = json!({ "orders": [{ "name": "Forward", "argument": -2 }] });
+    let strategy = json!({ "orders": [{ "name": "MinePlot", "argument": "BranchMining" }] });
+    let strategy_number = json!({ "orders": [{ "name": "MinePlot", "argument": 2 }] });
+    let strategy_unknown = json!({ "orders": [{ "name": "MinePlot", "argument": 7 }] });
+    expect(
+        &app,
+        vec![
+            (get("/api/v1/turtles/miner/orders"), 200),
+            (get("/api/v1/turtles/nobody/orders"), 404),
+            (
+                post("/api/v1/turtles/miner/orders", json!({ "orders": "Up,2" })),
+                200,
+            ),
+            (post("/api/v1/turtles/miner/orders", commands.clone()), 200),
+            (post("/api/v1/turtles/nobody/orders", commands.clone()), 404),
+            (
+                post("/api/v1/turtles/miner/orders", json!({ "orders": "Fly" })),
+                422,
+            ),
+            (post("/api/v1/turtles/miner/orders", negative), 422),
+            (post("/api/v1/turtles/miner/orders", strategy), 200),
+            (post("/api/v1/turtles/miner/orders", strategy_number), 200),
+            (post("/api/v1/turtles/miner/orders", strategy_unknown), 422),
+            (
+                post(
+                    "/api/v1/turtles/miner/orders",
+                    json!({ "priority": "High" }),
+                ),
+                422,
+            ),
+            (
+                put("/api/v1/turtles/miner/orders", json!({ "orders": "Home" })),
+                200,
+            ),
+            (put("/api/v1/turtles/nobody/orders", commands), 404),
+            (
+                put("/api/v1/turtles/miner/orders", json!({ "orders": 2 })),
+                422,
+            ),
+            (delete("/api/v1/turtles/miner/orders/42"), 404),
+            (delete("/api/v1/turtles/nobody/orders/1"), 404),
+        ],
+    )
+    .await;
+
+    // Only the order queued by the PUT is left
+    let (_, body) = call(&app, get("/api/v1/turtles/miner/orders")).await;
+    let orders: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let id = orders[0]["id"].as_u64().unwrap();
+    let uri = format!("/api/v1/turtles/miner/orders/{}", id);
+    expect(&app, vec![(delete(&uri), 200), (delete(&uri), 404)]).await;
+
+    // The listed commands can be sent back as they are
+    let program = json!({ "orders": "MinePlot,BranchMining\nForward,3" });
+    expect(
+        &app,
+        vec![(put("/api/v1/turtles/miner/orders", program), 200)],
+    )
+    .await;
+    let (_, body) = call(&app, get("/api/v1/turtles/miner/orders")).await;
+    let orders: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let commands: Vec<_> = orders
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|order| order["command"].clone())
+        .collect();
+    assert_eq!(commands[0], json!({ "name": "MinePlot", "argument": 2 }));
+    let body = json!({ "orders": commands });
+    expect(&app, vec![(put("/api/v1/turtles/miner/orders", body), 200)]).await;
+    let (_, body) = call(&app, get("/api/v1/turtles/miner/orders")).await;
+    let listed: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(listed[0]["command"], commands[0]);
+    assert_eq!(listed[1]["command"], commands[1]);
+}
+
+#[actix_web::test]
+async fn test_turtle_telemetry() {
+    let config = test_config();
+    let app = init_app!(test_storage(&config).await, config.clone());
+    expect(
+        &app,
+        vec![
+            (get("/api/v1/turtles/miner/telemetry"), 200),
+            (get("/api/v1/turtles/nobody/telemetry"), 404),
+            (
+                get("/api/v1/turtles/miner/telemetry/fuel_level?step=60"),
+                200,
+            ),
+            (get("/api/v1/turtles/nobody/telemetry/fuel_level"), 404),
+            (
+                get("/api/v1/turtles/miner/telemetry/fuel_level?step=0"),
+                422,
+            ),
+            (
+                get("/api/v1/turtles/miner/telemetry/fuel_level?from=20&to=10"),
+                422,
+            ),
+            (
+                get("/api/v1/turtles/miner/telemetry/fuel_level?step=soon"),
+                422,
+            ),
+        ],
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_mining_plots() {
+    let config = test_config();
+    let app = init_app!(test_storage(&config).await, config.clone());
+    expect(
+        &app,
+        vec![
+            (get("/api/v1/mining_plots"), 200),
+            (get("/api/v1/mining_plots/0/yields"), 200),
+            (get("/api/v1/mining_plots/42/yields"), 404),
+            (
+                put("/api/v1/mining_plots/0/turtle", json!({ "turtle": null })),
+                200,
+            ),
+            (
+                put("/api/v1/mining_plots/42/turtle", json!({ "turtle": null })),
+                404,
+            ),
+            (
+                put("/api/v1/mining_plots/0/turtle", json!({ "turtle": 3 })),
+                422,
+            ),
+        ],
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_mining_areas() {
+    let config = test_config();
+    let app = init_app!(test_storage(&config).await, config.clone());
+    let area = json!({
+        "origin": { "x": -10, "y": 60, "z": -6 },
+        "plot_size": 4,
+        "min_y": 42,
+        "direction": "West",
+        "chest_position": config.world.chest_position,
+    });
+    expect(
+        &app,
+        vec![
+            (put("/api/v1/mining_areas/west", area.clone()), 200),
+            (get("/api/v1/mining_areas"), 200),
+            (get("/api/v1/mining_areas/west"), 200),
+            (get("/api/v1/mining_areas/default"), 200),
+            (get("/api/v1/mining_areas/east"), 404),
+            (put("/api/v1/mining_areas/default", area), 422),
+            (
+                put("/api/v1/mining_areas/east", json!({ "plot_size": 4 })),
+                422,
+            ),
+        ],
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_stations() {
+    let config = test_config();
+    let app = init_app!(test_storage(&config).await, config.clone());
+    let station = json!({
+        "kind": "Fuel",
+        "position": config.world.chest_position,
+        "facing": "North",
+    });
+    let mut unknown_kind = station.clone();
+    unknown_kind["kind"] = json!("Lava");
+    expect(
+        &app,
+        vec![
+            (put("/api/v1/stations/fuel", station), 200),
+            (get("/api/v1/stations"), 200),
+            (put("/api/v1/stations/lava", unknown_kind), 422),
+            (delete("/api/v1/stations/fuel"), 200),
+            (delete("/api/v1/stations/fuel"), 404),
+        ],
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_blocks() {
+    let config = test_config();
+    let app = init_app!(test_storage(&config).await, config.clone());
+    expect(
+        &app,
+        vec![
+            (get("/api/v1/blocks?from=0,0,0&to=4,4,4"), 200),
+            (get("/api/v1/blocks?from=0,0,0"), 422),
+            (get("/api/v1/blocks?from=0,0&to=4,4,4"), 422),
+            (get("/api/v1/blocks?from=0,0,0&to=1000,1000,1000"), 422),
+        ],
+    )
+    .await;
+}
@@ -0,0 +1,238 @@
+// Each test file uses only some of the helpers
+#![allow(dead_code)]
+
+use actix_http::Request;
+use actix_web::{
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::StatusCode,
+    test, Error,
+};
+use cc_api::{
+    config::{Config, DatabaseBackend},
+    mining_areas::DEFAULT_AREA,
+    mining_plots::MiningPlot,
+    mining_strategies::StrategyName,
+    persistance::Storage,
+    simulator::{SimulatedTurtle, World, BEDROCK, COAL, FUEL_SLOT, STONE},
+    turtle::GpsFix,
+    utils::{Direction, Position},
+};
+use std::fmt::Debug;
+
+/// Build the app with every route, as the server does.
+#[macro_export]
+macro_rules! init_app {
+    ($storage:expr, $config:expr) => {
+        actix_web::test::init_service(
+            actix_web::App::new()
+                .app_data(actix_web::web::Data::from($storage))
+                .app_data(actix_web::web::Data::new($config))
+                .app_data(actix_web::web::Data::new(
+                    cc_api::locks::TurtleLocks::default(),
+                ))
+                .app_data(actix_web::web::Data::new(
+                    cc_api::script::ScriptCache::default(),
+                ))
+                .configure(cc_api::routes::configure),
+        )
+        .await
+    };
+}
+
+/// A small world around the origin, with the mining area 10 blocks north of the chests.
+pub fn test_config() -> Config {
+    let mut config = Config::default();
+    config.database.backend = DatabaseBackend::Memory;
+    config.world.chest_position = Position { x: 0, y: 64, z: 0 };
+    config.world.mining_position = Position {
+        x: 0,
+        y: 60,
+        z: -10,
+    };
+    config.world.min_mining_y = 42;
+    config.world.plots_wide = 2;
+    config.world.plot_size = 4;
+    config
+}
+
+/// Stone from the surface down to the bedrock, with a chest on each side of the home position.
+pub fn test_world(config: &Config) -> World {
+    let mut world = World::default();
+    let surface = config.world.chest_position.y - 1;
+    let bedrock = config.world.min_mining_y - 2;
+    world.fill(
+        Position {
+            x: -20,
+            y: bedrock + 1,
+            z: -40,
+        },
+        Position {
+            x: 20,
+            y: surface,
+            z: 10,
+        },
+        STONE,
+    );
+    world.fill(
+        Position {
+            x: -20,
+            y: bedrock,
+            z: -40,
+        },
+        Position {
+            x: 20,
+            y: bedrock,
+            z: 10,
+        },
+        BEDROCK,
+    );
+    let home = config.world.chest_position;
+    world.place_chest(home + Position { x: -1, y: 0, z: 0 });
+    world.place_chest(home + Position { x: 1, y: 0, z: 0 });
+    world
+}
+
+/// Create the next quarry of the default area for `turtle`, as the turtles do.
+pub async fn create_plot(storage: &dyn Storage, config: &Config, turtle: &str) -> MiningPlot {
+    MiningPlot::create(
+        storage,
+        DEFAULT_AREA,
+        &config.world,
+        turtle,
+        StrategyName::Quarry,
+    )
+    .await
+    .unwrap()
+    .unwrap()
+}
+
+pub async fn call<S, B>(app: &S, request: Request) -> (StatusCode, String)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+    B::Error: Debug,
+{
+    let response = test::call_service(app, request).await;
+    let status = response.status();
+    let body = test::read_body(response).await;
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+/// A simulated turtle talking to the server like `main.lua`.
+pub struct SimulatedClient {
+    pub name: String,
+    pub turtle: SimulatedTurtle,
+}
+
+impl SimulatedClient {
+    /// A turtle waiting at the home position, with coal to refuel.
+    pub fn new(name: &str, config: &Config) -> Self {
+        let mut turtle = SimulatedTurtle::new(config.world.chest_position, Direction::North, 10000);
+        turtle.give(FUEL_SLOT, COAL, 64);
+        turtle.ores = config.world.ores.clone();
+        SimulatedClient {
+            name: name.to_string(),
+            turtle,
+        }
+    }
+
+    async fn post_info<S, B>(&self, app: &S, topic: &str, info: &str)
+    where
+        S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+        B: MessageBody,
+        B::Error: Debug,
+    {
+        let request = test::TestRequest::post()
+            .uri(&format!("/info/{}/{}", self.name, topic))
+            .set_form(&[("info", info)])
+            .to_request();
+        let (status, _) = call(app, request).await;
+        assert!(status.is_success(), "Unable to post info {}", topic);
+    }
+
+    async fn post_gps<S, B>(&self, app: &S, fix: &GpsFix) -> String
+    where
+        S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+        B: MessageBody,
+        B::Error: Debug,
+    {
+        let request = test::TestRequest::post()
+            .uri(&format!("/gps/{}", self.name))
+            .set_form(fix)
+            .to_request();
+        call(app, request).await.1
+    }
+
+    /// One iteration of the main loop: post the infos, request orders and run them.
+    pub async fn cycle<S, B>(&mut self, app: &S, world: &mut World, config: &Config)
+    where
+        S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+        B: MessageBody,
+        B::Error: Debug,
+    {
+        if config.script.gps {
+            let fix = self.turtle.locate(false, None);
+            self.post_gps(app, &fix).await;
+        }
+        for (topic, info) in self.turtle.cycle_infos(config.world.min_fuel_level) {
+            self.post_info(app, &topic, &info).await;
+        }
+        let request = test::TestRequest::post()
+            .uri(&format!("/inventory/{}", self.name))
+            .set_json(&self.turtle.report_inventory())
+            .to_request();
+        let (status, _) = call(app, request).await;
+        assert!(status.is_success(), "Unable to post the inventory");
+        let request = test::TestRequest::get()
+            .uri(&format!("/request/{}", self.name))
+            .to_request();
+        let (status, orders) = call(app, request).await;
+        assert!(status.is_success(), "Request failed: {}", orders);
+        let ack = self.turtle.respond(world, &orders);
+        for (topic, info) in self.turtle.take_reports() {
+            self.post_info(app, &topic, &info).await;
+        }
+        for fix in self.turtle.take_fixes() {
+            self.post_gps(app, &fix).await;
+        }
+        for inspection in self.turtle.take_inspections() {
+            let request = test::TestRequest::post()
+                .uri(&format!("/inspect/{}", self.name))
+                .set_form(&inspection)
+                .to_request();
+            let (_, body) = call(app, request).await;
+            assert_eq!(body, "ok");
+        }
+        for ore in self.turtle.take_dug_ores() {
+            let request = test::TestRequest::post()
+                .uri(&format!("/dug/{}", self.name))
+                .set_form(&ore)
+                .to_request();
+            let (_, body) = call(app, request).await;
+            assert_eq!(body, "ok");
+        }
+        for report in self.turtle.take_station_reports() {
+            // The chests of the home aren't stations
+            let request = test::TestRequest::post()
+                .uri(&format!("/station/{}", self.name))
+                .set_form(&report)
+                .to_request();
+            call(app, request).await;
+        }
+        if let Some(ack) = ack {
+            let request = test::TestRequest::post()
+                .uri(&format!("/ack/{}", self.name))
+                .set_form(&[
+                    ("batch", ack.batch.to_string()),
+                    ("done", ack.done.to_string()),
+                    ("steps", ack.steps.to_string()),
+                    ("fuel", ack.fuel.unwrap_or_default().to_string()),
+                    ("dug", ack.dug),
+                ])
+                .to_request();
+            let (_, body) = call(app, request).await;
+            assert_eq!(body, "ok");
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use actix_web::test::TestRequest;
+use cc_api::{
+    mining_plots::MiningPlot,
+    persistance::{MemoryStorage, Storage},
+    turtle::Turtle,
+};
+use futures::{executor::block_on, future::join_all};
+use std::{collections::HashSet, sync::Arc};
+
+mod common;
+
+use common::{call, create_plot, test_config, test_world, SimulatedClient};
+
+const THREADS: usize = 16;
+const PLOTS_PER_THREAD: usize = 50;
+
+/// Whether the squares of two plots share a column of blocks.
+fn overlap(first: &MiningPlot, second: &MiningPlot, plot_size: i32) -> bool {
+    let dx = (first.position.x - second.position.x).abs();
+    let dz = (first.position.z - second.position.z).abs();
+    dx < plot_size && dz < plot_size
+}
+
+#[test]
+fn test_concurrent_plot_allocation() {
+    let mut config = test_config();
+    config.world.plots_wide = (THREADS * PLOTS_PER_THREAD) as i32;
+    let config = &config;
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+
+    std::thread::scope(|scope| {
+        for thread in 0..THREADS {
+            let storage = storage.clone();
+            scope.spawn(move || {
+                let name = format!("turtle-{}", thread);
+                for _ in 0..PLOTS_PER_THREAD {
+                    block_on(create_plot(storage.as_ref(), config, &name));
+                }
+            });
+        }
+    });
+
+    let plots = block_on(storage.list_plots()).unwrap();
+    assert_eq!(plots.len(), THREADS * PLOTS_PER_THREAD);
+    let ids: HashSet<_> = plots.iter().map(|plot| plot.id).collect();
+    assert_eq!(ids.len(), plots.len());
+    for (index, first) in plots.iter().enumerate() {
+        for second in &plots[index + 1..] {
+            assert!(
+                !overlap(first, second, config.world.plot_size),
+                "Plots {} and {} overlap",
+                first.id,
+                second.id
+            );
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_many_turtles_polling() {
+    const TURTLES: usize = 16;
+    const CYCLES: usize = 4;
+    let mut config = test_config();
+    config.world.plots_wide = 4;
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    // Each turtle digs its own copy of the world, only the server is shared
+    let world = test_world(&config);
+    let mut clients: Vec<_> = (0..TURTLES)
+        .map(|index| {
+            let name = format!("turtle-{}", index);
+            (SimulatedClient::new(&name, &config), world.clone())
+        })
+        .collect();
+
+    join_all(clients.iter_mut().map(|(client, world)| {
+        let (app, config) = (&app, &config);
+        async move {
+            for _ in 0..CYCLES {
+                client.cycle(app, world, config).await;
+            }
+        }
+    }))
+    .await;
+
+    for (client, _) in &clients {
+        let request = TestRequest::get()
+            .uri(&format!("/api/v1/turtles/{}", client.name))
+            .to_request();
+        let (_, body) = call(&app, request).await;
+        let turtle: Turtle = serde_json::from_str(&body).unwrap();
+        assert_eq!(turtle.pos, client.turtle.pos, "{} is lost", client.name);
+        assert!(turtle.batch.is_none());
+    }
+    let plots = storage.list_plots().await.unwrap();
+    let owners: HashSet<_> = plots
+        .iter()
+        .filter_map(|plot| plot.current_turtle.clone())
+        .collect();
+    assert_eq!(owners.len(), TURTLES);
+    assert_eq!(plots.len(), TURTLES);
+}
+
+#[actix_web::test]
+async fn test_concurrent_plot_swaps() {
+    const SWAPS: usize = 20;
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    for name in ["first", "second"] {
+        storage
+            .insert_turtle(&Turtle::default(name.to_string(), &config.world))
+            .await
+            .unwrap();
+        create_plot(storage.as_ref(), &config, name).await;
+    }
+
+    // Each plot goes back and forth between the turtles, both are locked by every swap
+    join_all((0..SWAPS).map(|swap| {
+        let app = &app;
+        async move {
+            let (plot, turtle) = [(0, "second"), (1, "first")][swap % 2];
+            let request = TestRequest::put()
+                .uri(&format!("/api/v1/mining_plots/{}/turtle", plot))
+                .set_json(&serde_json::json!({ "turtle": turtle }))
+                .to_request();
+            let (status, _) = call(app, request).await;
+            assert_eq!(status.as_u16(), 200);
+        }
+    }))
+    .await;
+
+    let plots = storage.list_plots().await.unwrap();
+    let owners: Vec<_> = plots
+        .iter()
+        .filter_map(|plot| plot.current_turtle.as_deref())
+        .collect();
+    let unique: HashSet<_> = owners.iter().collect();
+    assert_eq!(unique.len(), owners.len(), "{:?}", owners);
+}
@@ -0,0 +1,779 @@
+use actix_web::test;
+use cc_api::{
+    fuel::FuelProjection,
+    inventory::Inventory,
+    mining_areas::MiningArea,
+    mining_plots::MiningPlot,
+    mining_strategies::StrategyName,
+    order_queue::QueuedOrder,
+    persistance::{MemoryStorage, Storage},
+    simulator::{BEDROCK, COAL, FUEL_SLOT, STACK_SIZE, STONE},
+    stations::Station,
+    telemetry::{Telemetry, TelemetryPoint, TelemetrySample, TelemetryTopic},
+    turtle::Turtle,
+    utils::{unix_timestamp, Position},
+    world_map::{Block, AIR},
+};
+use std::sync::Arc;
+
+const IRON_ORE: &str = "minecraft:iron_ore";
+
+mod common;
+
+use common::{call, test_config, test_world, SimulatedClient};
+
+#[actix_web::test]
+async fn test_simulated_turtle_mines_plots() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("simulated", &config);
+
+    for _ in 0..20 {
+        client.cycle(&app, &mut world, &config).await;
+
+        let request = test::TestRequest::get()
+            .uri("/api/v1/turtles/simulated")
+            .to_request();
+        let (_, body) = call(&app, request).await;
+        let turtle: Turtle = serde_json::from_str(&body).unwrap();
+        assert_eq!(turtle.pos, client.turtle.pos);
+        assert_eq!(turtle.direction, client.turtle.direction);
+    }
+
+    let plots: Vec<MiningPlot> = storage.list_plots().await.unwrap();
+    let (current, finished) = plots.split_last().unwrap();
+    assert!(!finished.is_empty());
+    assert!(finished.iter().all(|plot| plot.current_turtle.is_none()));
+    assert_eq!(current.current_turtle.as_deref(), Some("simulated"));
+
+    // Every segment of the first plot has been mined
+    let plot = plots[0].position;
+    for segment in 0..=config.world.max_depth_segment() {
+        let y = -((segment * config.world.plot_depth) as i32);
+        for x in 0..config.world.plot_size {
+            for z in 0..config.world.plot_size {
+                let position = plot + Position { x, y, z: -z };
+                assert!(world.is_air(&position), "{:?} was not mined", position);
+            }
+        }
+    }
+    assert!(client.turtle.count(cc_api::simulator::STONE) > 0);
+}
+
+#[actix_web::test]
+async fn test_server_follows_failed_moves() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    // In the way of the first segment of the first plot
+    world.set_block(
+        config.world.mining_position + Position { x: 0, y: 0, z: -2 },
+        BEDROCK,
+    );
+    let mut client = SimulatedClient::new("blocked", &config);
+
+    for _ in 0..10 {
+        client.cycle(&app, &mut world, &config).await;
+
+        let turtle = storage.find_turtle("blocked").await.unwrap().unwrap();
+        assert_eq!(turtle.pos, client.turtle.pos);
+        assert_eq!(turtle.direction, client.turtle.direction);
+        assert!(turtle.batch.is_none());
+    }
+    let turtle = storage.find_turtle("blocked").await.unwrap().unwrap();
+    assert_eq!(turtle.infos["stuck"], "forward");
+}
+
+#[actix_web::test]
+async fn test_gps_corrects_position() {
+    let mut config = test_config();
+    config.script.gps = true;
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("lost", &config);
+    client.cycle(&app, &mut world, &config).await;
+
+    // Moved by hand, the server doesn't know about it
+    client.turtle.pos = client.turtle.pos + Position { x: 3, y: 0, z: 1 };
+    for _ in 0..5 {
+        client.cycle(&app, &mut world, &config).await;
+        let turtle = storage.find_turtle("lost").await.unwrap().unwrap();
+        assert_eq!(turtle.pos, client.turtle.pos);
+    }
+    let turtle = storage.find_turtle("lost").await.unwrap().unwrap();
+    assert_eq!(turtle.drifts.len(), 1);
+    assert_eq!(
+        turtle.drifts[0].actual - turtle.drifts[0].expected,
+        Position { x: 3, y: 0, z: 1 }
+    );
+}
+
+#[actix_web::test]
+async fn test_ore_veins() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    // A vein from the corner of the first plot going west, out of the plot
+    let corner = config.world.mining_position;
+    let vein: Vec<_> = [(0, 0), (-1, 0), (-2, 0), (-1, 1)]
+        .iter()
+        .map(|(x, y)| corner + Position { x: *x, y: *y, z: 0 })
+        .collect();
+    for position in &vein {
+        world.set_block(*position, IRON_ORE);
+    }
+    let mut client = SimulatedClient::new("miner", &config);
+
+    for _ in 0..8 {
+        client.cycle(&app, &mut world, &config).await;
+    }
+
+    for position in &vein {
+        assert!(world.is_air(position), "{:?} was not mined", position);
+    }
+    let far = corner + Position { x: -4, y: 0, z: 0 };
+    assert_eq!(world.block(&far), Some(STONE));
+    assert_eq!(client.turtle.count(IRON_ORE), 4);
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/mining_plots/0/yields")
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    let yields: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(yields["total"][IRON_ORE], 4);
+    assert_eq!(yields["segments"][0]["segment"], 0);
+    assert_eq!(yields["segments"][0]["blocks"][IRON_ORE], 4);
+    assert!(yields["total"][STONE].as_u64().unwrap() > 0);
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/mining_plots/42/yields")
+        .to_request();
+    let (status, _) = call(&app, request).await;
+    assert_eq!(status.as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_branch_mining() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("brancher", &config);
+    client.cycle(&app, &mut world, &config).await;
+
+    let request = test::TestRequest::post()
+        .uri("/order/brancher")
+        .set_form(&[("orders", "MinePlot,BranchMining")])
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    assert_eq!(body, "ok");
+    for _ in 0..3 {
+        client.cycle(&app, &mut world, &config).await;
+    }
+
+    let plot = storage.find_plot(0).await.unwrap().unwrap();
+    assert_eq!(plot.strategy, StrategyName::BranchMining);
+    let turtle = storage.find_turtle("brancher").await.unwrap().unwrap();
+    assert_eq!(turtle.strategy, Some(StrategyName::BranchMining));
+    // The main tunnel and the branches are two blocks high, the stone between stays
+    let corner = config.world.mining_position;
+    for offset in [(0, 0, -1), (0, 1, -2), (3, 0, 0), (2, 1, -3)] {
+        let (x, y, z) = offset;
+        assert!(
+            world.is_air(&(corner + Position { x, y, z })),
+            "{:?}",
+            offset
+        );
+    }
+    for offset in [(1, 0, -1), (2, 0, -2), (0, -1, -1)] {
+        let (x, y, z) = offset;
+        let position = corner + Position { x, y, z };
+        assert_eq!(world.block(&position), Some(STONE), "{:?}", offset);
+    }
+}
+
+#[actix_web::test]
+async fn test_world_map() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("explorer", &config);
+    client.cycle(&app, &mut world, &config).await;
+
+    let request = test::TestRequest::post()
+        .uri("/order/explorer")
+        .set_form(&[(
+            "orders",
+            "Down\nInspectDown\nRight\nInspect\nLeft,2\nInspectUp",
+        )])
+        .to_request();
+    call(&app, request).await;
+    client.cycle(&app, &mut world, &config).await;
+
+    let home = config.world.chest_position;
+    let request = test::TestRequest::get()
+        .uri(&format!(
+            "/api/v1/blocks?from={},{},{}&to={},{},{}",
+            home.x - 2,
+            home.y - 2,
+            home.z,
+            home.x + 2,
+            home.y,
+            home.z
+        ))
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    let mut blocks: Vec<Block> = serde_json::from_str(&body).unwrap();
+    blocks.sort_by_key(|block| (block.position.x, block.position.y));
+    let names: Vec<_> = blocks
+        .iter()
+        .map(|block| (block.position - home, block.name.as_str()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            (Position { x: 0, y: -2, z: 0 }, STONE),
+            (Position { x: 0, y: 0, z: 0 }, AIR),
+            (Position { x: 1, y: -1, z: 0 }, STONE),
+        ]
+    );
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/blocks?from=0,0,0&to=1000,1000,1000")
+        .to_request();
+    let (status, _) = call(&app, request).await;
+    assert_eq!(status.as_u16(), 422);
+}
+
+#[actix_web::test]
+async fn test_offline_turtle_plot() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut lost = SimulatedClient::new("lost", &config);
+    for _ in 0..3 {
+        lost.cycle(&app, &mut world, &config).await;
+    }
+    let segment = storage
+        .find_plot(0)
+        .await
+        .unwrap()
+        .unwrap()
+        .mined_depth_segment;
+    // Not heard of since long before the timeout
+    let mut turtle = storage.find_turtle("lost").await.unwrap().unwrap();
+    turtle.last_seen -= config.script.offline_timeout + 1;
+    storage.save_turtle(&turtle).await.unwrap();
+
+    let mut client = SimulatedClient::new("spare", &config);
+    for _ in 0..3 {
+        client.cycle(&app, &mut world, &config).await;
+    }
+    let plots = storage.list_plots().await.unwrap();
+    assert_eq!(plots.len(), 1);
+    assert_eq!(plots[0].current_turtle.as_deref(), Some("spare"));
+    assert_eq!(plots[0].mined_depth_segment, segment);
+    let turtle = storage.find_turtle("lost").await.unwrap().unwrap();
+    assert!(turtle.offline);
+
+    // Give the plot back, the spare turtle gets a new one next time
+    let request = test::TestRequest::put()
+        .uri("/api/v1/mining_plots/0/turtle")
+        .set_json(&serde_json::json!({ "turtle": "lost" }))
+        .to_request();
+    let (status, body) = call(&app, request).await;
+    assert_eq!(status.as_u16(), 200);
+    let plot: MiningPlot = serde_json::from_str(&body).unwrap();
+    assert_eq!(plot.current_turtle.as_deref(), Some("lost"));
+    assert!(storage.find_turtle_plot("spare").await.unwrap().is_none());
+
+    let request = test::TestRequest::put()
+        .uri("/api/v1/mining_plots/0/turtle")
+        .set_json(&serde_json::json!({ "turtle": null }))
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    let plot: MiningPlot = serde_json::from_str(&body).unwrap();
+    assert_eq!(plot.current_turtle, None);
+
+    for (uri, turtle) in [
+        ("/api/v1/mining_plots/0/turtle", "nobody"),
+        ("/api/v1/mining_plots/42/turtle", "lost"),
+    ] {
+        let request = test::TestRequest::put()
+            .uri(uri)
+            .set_json(&serde_json::json!({ "turtle": turtle }))
+            .to_request();
+        let (status, _) = call(&app, request).await;
+        assert_eq!(status.as_u16(), 404);
+    }
+}
+
+#[actix_web::test]
+async fn test_mining_areas() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("miner", &config);
+    for _ in 0..3 {
+        client.cycle(&app, &mut world, &config).await;
+    }
+
+    // West of the home, the plots spreading west and north
+    let origin = Position {
+        x: -10,
+        y: 60,
+        z: -6,
+    };
+    let area = serde_json::json!({
+        "origin": origin,
+        "plot_size": 4,
+        "min_y": 42,
+        "direction": "West",
+        "chest_position": config.world.chest_position,
+    });
+    let request = test::TestRequest::put()
+        .uri("/api/v1/mining_areas/west")
+        .set_json(&area)
+        .to_request();
+    let (status, _) = call(&app, request).await;
+    assert_eq!(status.as_u16(), 200);
+    let request = test::TestRequest::put()
+        .uri("/api/v1/turtles/miner/area")
+        .set_json(&serde_json::json!({ "area": "west" }))
+        .to_request();
+    let (status, _) = call(&app, request).await;
+    assert_eq!(status.as_u16(), 200);
+    client.cycle(&app, &mut world, &config).await;
+
+    let plots = storage.list_plots().await.unwrap();
+    assert_eq!(plots.len(), 2);
+    assert_eq!(plots[0].area, "default");
+    assert_eq!(plots[0].current_turtle, None);
+    assert!(!plots[0].finished);
+    assert_eq!(plots[1].area, "west");
+    assert_eq!(plots[1].index, 0);
+    assert_eq!(plots[1].position, origin);
+    assert!(world.is_air(&(origin + Position { x: -3, y: 0, z: -3 })));
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/mining_areas")
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    let areas: Vec<MiningArea> = serde_json::from_str(&body).unwrap();
+    let names: Vec<_> = areas.iter().map(|area| area.name.as_str()).collect();
+    assert_eq!(names, ["default", "west"]);
+
+    let mut too_deep = area.clone();
+    too_deep["min_y"] = serde_json::json!(70);
+    for (uri, body) in [
+        ("/api/v1/mining_areas/default", area),
+        ("/api/v1/mining_areas/deep", too_deep),
+    ] {
+        let request = test::TestRequest::put()
+            .uri(uri)
+            .set_json(&body)
+            .to_request();
+        let (status, _) = call(&app, request).await;
+        assert_eq!(status.as_u16(), 422);
+    }
+    let request = test::TestRequest::put()
+        .uri("/api/v1/turtles/miner/area")
+        .set_json(&serde_json::json!({ "area": "nowhere" }))
+        .to_request();
+    let (status, _) = call(&app, request).await;
+    assert_eq!(status.as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_stations() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("hauler", &config);
+    let home = config.world.chest_position;
+
+    // A full deposit chest east of the home, an empty one further south and coal to the west
+    let stations = [
+        ("full", "Deposit", Position { x: 3, y: 0, z: 0 }, "East"),
+        ("empty", "Deposit", Position { x: 0, y: 0, z: 5 }, "South"),
+        ("coal", "Fuel", Position { x: -3, y: 0, z: 0 }, "West"),
+    ];
+    for (name, kind, offset, facing) in stations {
+        let request = test::TestRequest::put()
+            .uri(&format!("/api/v1/stations/{}", name))
+            .set_json(&serde_json::json!({
+                "kind": kind,
+                "position": home + offset,
+                "facing": facing,
+            }))
+            .to_request();
+        let (status, _) = call(&app, request).await;
+        assert_eq!(status.as_u16(), 200);
+    }
+    let full_chest = home + Position { x: 4, y: 0, z: 0 };
+    let empty_chest = home + Position { x: 0, y: 0, z: 6 };
+    let coal_chest = home + Position { x: -4, y: 0, z: 0 };
+    for chest in [full_chest, empty_chest, coal_chest] {
+        world.place_chest(chest);
+    }
+    assert_eq!(world.store(&full_chest, STONE, 27 * STACK_SIZE), 0);
+    world.store(&coal_chest, COAL, STACK_SIZE);
+
+    // The full chest is tried first, then skipped once the turtle reported it. The first cycle
+    // only registers the turtle.
+    for slot in 0..FUEL_SLOT {
+        client.turtle.give(slot, STONE, 1);
+    }
+    for _ in 0..3 {
+        client.cycle(&app, &mut world, &config).await;
+    }
+    assert_eq!(world.chest_content(&empty_chest)[STONE], FUEL_SLOT as u32);
+    assert_eq!(client.turtle.count(STONE), 0);
+    let request = test::TestRequest::get()
+        .uri("/api/v1/stations")
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    let stations: Vec<Station> = serde_json::from_str(&body).unwrap();
+    let capacities: Vec<_> = stations
+        .iter()
+        .map(|station| (station.name.as_str(), station.capacity))
+        .collect();
+    assert_eq!(
+        capacities,
+        [("coal", None), ("empty", Some(26)), ("full", Some(0))]
+    );
+
+    // Low on fuel without coal left, the turtle takes some from the fuel station
+    client.turtle.fuel = 100;
+    client.turtle.inventory[FUEL_SLOT] = None;
+    client.cycle(&app, &mut world, &config).await;
+    assert_eq!(client.turtle.pos, home + Position { x: -3, y: 0, z: 0 });
+    assert!(client.turtle.fuel > config.world.min_fuel_level);
+    assert_eq!(world.chest_content(&coal_chest).get(COAL), None);
+    let coal = storage.list_stations().await.unwrap().remove(0);
+    assert_eq!(coal.capacity, Some(0));
+
+    let request = test::TestRequest::delete()
+        .uri("/api/v1/stations/full")
+        .to_request();
+    let (status, _) = call(&app, request).await;
+    assert_eq!(status.as_u16(), 200);
+    let (status, _) = call(
+        &app,
+        test::TestRequest::delete()
+            .uri("/api/v1/stations/full")
+            .to_request(),
+    )
+    .await;
+    assert_eq!(status.as_u16(), 404);
+}
+
+#[actix_web::test]
+async fn test_valuable_items() {
+    let mut config = test_config();
+    config.world.valuable_items = vec![IRON_ORE.to_string()];
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("careful", &config);
+    for _ in 0..3 {
+        client.cycle(&app, &mut world, &config).await;
+    }
+    assert_ne!(client.turtle.pos, config.world.chest_position);
+
+    // A single ore is enough to go back to the chests
+    client.turtle.give(0, IRON_ORE, 1);
+    client.cycle(&app, &mut world, &config).await;
+    assert_eq!(client.turtle.pos, config.world.chest_position);
+    let west_chest = config.world.chest_position + Position { x: -1, y: 0, z: 0 };
+    assert_eq!(world.chest_content(&west_chest)[IRON_ORE], 1);
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/turtles/careful/inventory")
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    let inventory: Inventory = serde_json::from_str(&body).unwrap();
+    assert_eq!(inventory.slots[0].as_ref().unwrap().name, IRON_ORE);
+
+    let mut truncated = client.turtle.report_inventory();
+    truncated.slots.pop();
+    let request = test::TestRequest::post()
+        .uri("/inventory/careful")
+        .set_json(&truncated)
+        .to_request();
+    let (status, _) = call(&app, request).await;
+    assert_eq!(status.as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn test_fuel_planner() {
+    let mut config = test_config();
+    config.world.min_fuel_level = 0;
+    config.world.fuel_reserve = 2;
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("thrifty", &config);
+    client.turtle.inventory[FUEL_SLOT] = None;
+    client.turtle.fuel = 60;
+    let home = config.world.chest_position;
+
+    // Enough fuel for the first segment only, the turtle comes back instead of mining the next one
+    for _ in 0..5 {
+        client.cycle(&app, &mut world, &config).await;
+        let diff = client.turtle.pos - home;
+        let distance = diff.x.abs() + diff.y.abs() + diff.z.abs();
+        assert!(
+            client.turtle.fuel >= distance,
+            "Stranded at {:?}",
+            client.turtle.pos
+        );
+    }
+    assert_eq!(client.turtle.pos, home);
+    let plots = storage.list_plots().await.unwrap();
+    assert_eq!(plots.len(), 1);
+    assert_eq!(plots[0].mined_depth_segment, 0);
+    assert_eq!(plots[0].current_turtle.as_deref(), Some("thrifty"));
+
+    let request = test::TestRequest::put()
+        .uri("/api/v1/turtles/thrifty/orders")
+        .set_json(&serde_json::json!({ "orders": "Forward,3" }))
+        .to_request();
+    let (status, _) = call(&app, request).await;
+    assert_eq!(status.as_u16(), 200);
+    let request = test::TestRequest::get()
+        .uri("/api/v1/turtles/thrifty/fuel")
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    let projection: FuelProjection = serde_json::from_str(&body).unwrap();
+    let fuel = client.turtle.fuel;
+    assert_eq!(projection.level, Some(fuel));
+    assert_eq!(projection.cost, 3);
+    assert_eq!(projection.projected, Some(fuel - 3));
+    assert_eq!(projection.return_cost, 3);
+    assert_eq!(projection.unplanned, 0);
+
+    // Refueled, it goes on with the next segment
+    client.turtle.fuel = 1000;
+    for _ in 0..2 {
+        client.cycle(&app, &mut world, &config).await;
+    }
+    let plots = storage.list_plots().await.unwrap();
+    assert_eq!(plots[0].mined_depth_segment, 1);
+}
+
+#[actix_web::test]
+async fn test_telemetry() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("reporter", &config);
+    for _ in 0..2 {
+        client.cycle(&app, &mut world, &config).await;
+    }
+
+    let post = |topic: &str, info: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/info/reporter/{}", topic))
+            .set_form(&[("info", info)])
+            .to_request()
+    };
+    for (topic, info, status) in [
+        ("fuelLevel", "lots", 422),
+        ("selected_slot", "0", 422),
+        ("isFull", "true", 200),
+        ("selected_slot", "3", 200),
+        ("mood", "cheerful", 200),
+    ] {
+        let (actual, _) = call(&app, post(topic, info)).await;
+        assert_eq!(actual.as_u16(), status, "{} = {}", topic, info);
+    }
+
+    let request = test::TestRequest::get()
+        .uri("/api/v1/turtles/reporter/telemetry")
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    let telemetry: Telemetry = serde_json::from_str(&body).unwrap();
+    assert_eq!(telemetry.fuel_level, Some(client.turtle.fuel));
+    assert_eq!(telemetry.is_full, Some(true));
+    assert_eq!(telemetry.selected_slot, Some(3));
+    let turtle = storage.find_turtle("reporter").await.unwrap().unwrap();
+    assert_eq!(turtle.infos["mood"], "cheerful");
+    assert!(!turtle.infos.contains_key("isFull"));
+
+    // The old topic names still read the telemetry
+    let request = test::TestRequest::get()
+        .uri("/info/reporter/fuellevel")
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    assert_eq!(body, client.turtle.fuel.to_string());
+}
+
+#[actix_web::test]
+async fn test_telemetry_history() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("historian", &config);
+    client.cycle(&app, &mut world, &config).await;
+
+    // Two values within a minute an hour ago, and one past the retention
+    let now = unix_timestamp();
+    let hour_ago = now - 3600;
+    for (time, value) in [
+        (now - config.telemetry.retention - 1, "1000"),
+        (hour_ago, "400"),
+        (hour_ago + 30, "300"),
+    ] {
+        let sample = TelemetrySample {
+            turtle: "historian".to_string(),
+            topic: TelemetryTopic::FuelLevel.to_string(),
+            time,
+            value: value.to_string(),
+        };
+        storage.add_telemetry_sample(&sample).await.unwrap();
+    }
+    let request = test::TestRequest::post()
+        .uri("/info/historian/fuel_level")
+        .set_form(&[("info", "250")])
+        .to_request();
+    let (status, _) = call(&app, request).await;
+    assert!(status.is_success());
+
+    let history = |query: &str| {
+        test::TestRequest::get()
+            .uri(&format!("/api/v1/turtles/historian/telemetry/{}", query))
+            .to_request()
+    };
+    let from = now - config.telemetry.retention - 10;
+    let (_, body) = call(&app, history(&format!("fuel_level?from={}", from))).await;
+    let points: Vec<TelemetryPoint> = serde_json::from_str(&body).unwrap();
+    let values: Vec<_> = points.iter().map(|point| point.last.as_str()).collect();
+    assert_eq!(values, ["400", "300", "250"]);
+
+    let query = format!("fuelLevel?step=60&to={}", now + 60);
+    let (_, body) = call(&app, history(&query)).await;
+    let points: Vec<TelemetryPoint> = serde_json::from_str(&body).unwrap();
+    assert_eq!(points.len(), 2);
+    assert_eq!(points[0].count, 2);
+    assert_eq!(points[0].mean, Some(350.0));
+    assert_eq!(points[1].last, "250");
+
+    for (query, status) in [
+        ("mood", 404),
+        ("fuel_level?step=0", 422),
+        ("fuel_level?step=1&from=0", 422),
+        ("fuel_level?from=10&to=5", 422),
+    ] {
+        let (actual, _) = call(&app, history(query)).await;
+        assert_eq!(actual.as_u16(), status, "{}", query);
+    }
+
+    // Custom topics have a history too
+    let request = test::TestRequest::post()
+        .uri("/info/historian/mood")
+        .set_form(&[("info", "cheerful")])
+        .to_request();
+    call(&app, request).await;
+    let (_, body) = call(&app, history("mood")).await;
+    let points: Vec<TelemetryPoint> = serde_json::from_str(&body).unwrap();
+    assert_eq!(points.len(), 1);
+    assert_eq!(points[0].last, "cheerful");
+
+    // The fuel acknowledged with a batch is recorded, the history was pruned a moment ago
+    let expired = TelemetrySample {
+        turtle: "historian".to_string(),
+        topic: TelemetryTopic::FuelLevel.to_string(),
+        time: now - config.telemetry.retention - 1,
+        value: "1000".to_string(),
+    };
+    storage.add_telemetry_sample(&expired).await.unwrap();
+    // The turtle sleeps first, then posts its fuel before mining a batch
+    for _ in 0..2 {
+        client.cycle(&app, &mut world, &config).await;
+    }
+    let samples = storage
+        .find_telemetry_samples("historian", "fuel_level", 0, now + 60)
+        .await
+        .unwrap();
+    assert_eq!(samples[0], expired);
+    let [.., posted, acknowledged] = samples.as_slice() else {
+        panic!("{:?}", samples);
+    };
+    assert_eq!(acknowledged.value, client.turtle.fuel.to_string());
+    assert_ne!(posted.value, acknowledged.value);
+}
+
+#[actix_web::test]
+async fn test_order_queue() {
+    let config = test_config();
+    let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+    let app = init_app!(storage.clone(), config.clone());
+    let mut world = test_world(&config);
+    let mut client = SimulatedClient::new("queued", &config);
+    client.cycle(&app, &mut world, &config).await;
+
+    // Added after the first sleep of the turtle
+    let request = test::TestRequest::post()
+        .uri("/order/queued")
+        .set_form(&[("orders", "Forward,2")])
+        .to_request();
+    let (_, body) = call(&app, request).await;
+    assert_eq!(body, "ok");
+    for body in [
+        serde_json::json!({ "orders": "Up", "priority": "High" }),
+        serde_json::json!({ "orders": "Left,2", "prepend": true }),
+        serde_json::json!({ "orders": "Home", "priority": "Low" }),
+    ] {
+        let request = test::TestRequest::post()
+            .uri("/api/v1/turtles/queued/orders")
+            .set_json(&body)
+            .to_request();
+        let (status, _) = call(&app, request).await;
+        assert_eq!(status.as_u16(), 200, "{}", body);
+    }
+    let orders = || {
+        test::TestRequest::get()
+            .uri("/api/v1/turtles/queued/orders")
+            .to_request()
+    };
+    let (_, body) = call(&app, orders()).await;
+    let queued: Vec<QueuedOrder> = serde_json::from_str(&body).unwrap();
+    let names: Vec<_> = queued
+        .iter()
+        .map(|order| order.command.to_string())
+        .collect();
+    assert_eq!(
+        names,
+        ["Up(1)", "Left(2)", "Sleep(2)", "Forward(2)", "Home(0)"]
+    );
+
+    let cancel = |id: u32| {
+        test::TestRequest::delete()
+            .uri(&format!("/api/v1/turtles/queued/orders/{}", id))
+            .to_request()
+    };
+    let home = queued.last().unwrap();
+    let (status, _) = call(&app, cancel(home.id)).await;
+    assert_eq!(status.as_u16(), 200);
+    let (status, _) = call(&app, cancel(home.id)).await;
+    assert_eq!(status.as_u16(), 404);
+
+    // Every queued order is sent, then the turtle goes back to its idle orders
+    client.cycle(&app, &mut world, &config).await;
+    let (_, body) = call(&app, orders()).await;
+    assert_eq!(body, "[]");
+    let expected = config.world.chest_position + Position { x: 0, y: 1, z: 2 };
+    assert_eq!(client.turtle.pos, expected);
+}